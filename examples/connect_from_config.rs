@@ -67,6 +67,7 @@ async fn main() {
     let db = Client::from_config(libsql_client::Config {
         url: url::Url::parse("libsql://localhost:8080").unwrap(),
         auth_token: None,
+        ..Default::default()
     })
     .await
     .unwrap();
@@ -0,0 +1,299 @@
+//! `BatchBuilder` builds batches whose steps only run under some conditions.
+//!
+//! Each step can depend on whether previous steps succeeded or failed, so that flows like
+//! "insert if not exists, then update" run in a single request with [`Client::execute_batch`].
+//! The HTTP backends send the conditions to the server. The local backend evaluates them
+//! itself, and so does the WebSocket backend, which then executes the steps one at a time
+//! on a single stream.
+//!
+//! # Examples
+//!
+//! ```
+//! # async fn f() -> libsql_client::Result<()> {
+//! use libsql_client::{args, BatchBuilder, Statement};
+//!
+//! let db = libsql_client::Client::in_memory()?;
+//! # db.execute("CREATE TABLE counters(name TEXT PRIMARY KEY, value INTEGER)").await?;
+//! let mut batch = BatchBuilder::new();
+//! let insert = batch.step(Statement::with_args(
+//!     "INSERT INTO counters VALUES (?, 1)",
+//!     args!["visits"],
+//! ));
+//! batch.step_if(
+//!     insert.error(),
+//!     Statement::with_args(
+//!         "UPDATE counters SET value = value + 1 WHERE name = ?",
+//!         args!["visits"],
+//!     ),
+//! );
+//! let result = db.execute_batch(batch).await?;
+//! assert!(result.step_results[0].is_some());
+//! assert!(result.step_results[1].is_none());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Client::execute_batch`]: crate::Client::execute_batch
+
+use serde::Serialize;
+
+use crate::Statement;
+#[cfg(any(feature = "local_backend", feature = "hrana_backend"))]
+use crate::{
+    proto::{self, StmtResult},
+    BatchResult, Error,
+};
+
+/// Condition on the outcome of previous steps of a batch
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchCond {
+    /// The step was executed and succeeded
+    Ok { step: u32 },
+    /// The step was executed and failed
+    Error { step: u32 },
+    /// The condition is false
+    Not { cond: Box<BatchCond> },
+    /// All the conditions are true
+    And { conds: Vec<BatchCond> },
+    /// At least one of the conditions is true
+    Or { conds: Vec<BatchCond> },
+}
+
+impl BatchCond {
+    /// True if both this condition and `other` are true
+    pub fn and(self, other: BatchCond) -> BatchCond {
+        BatchCond::And {
+            conds: vec![self, other],
+        }
+    }
+
+    /// True if this condition or `other` is true
+    pub fn or(self, other: BatchCond) -> BatchCond {
+        BatchCond::Or {
+            conds: vec![self, other],
+        }
+    }
+
+    /// Evaluates the condition given the outcomes of the previous steps:
+    /// `Some(true)` for a step which succeeded, `Some(false)` for a step which failed
+    /// and `None` for a step which was skipped.
+    #[cfg(any(feature = "local_backend", feature = "hrana_backend"))]
+    pub(crate) fn eval(&self, outcomes: &[Option<bool>]) -> bool {
+        let outcome = |step: &u32| outcomes.get(*step as usize).copied().flatten();
+        match self {
+            BatchCond::Ok { step } => outcome(step) == Some(true),
+            BatchCond::Error { step } => outcome(step) == Some(false),
+            BatchCond::Not { cond } => !cond.eval(outcomes),
+            BatchCond::And { conds } => conds.iter().all(|cond| cond.eval(outcomes)),
+            BatchCond::Or { conds } => conds.iter().any(|cond| cond.eval(outcomes)),
+        }
+    }
+}
+
+impl std::ops::Not for BatchCond {
+    type Output = BatchCond;
+
+    fn not(self) -> BatchCond {
+        BatchCond::Not {
+            cond: Box::new(self),
+        }
+    }
+}
+
+/// A step added to a [`BatchBuilder`], used to build conditions on its outcome
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step(u32);
+
+impl Step {
+    /// Index of the step in the batch, and in its [`BatchResult`]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Condition true if the step was executed and succeeded
+    pub fn ok(self) -> BatchCond {
+        BatchCond::Ok { step: self.0 }
+    }
+
+    /// Condition true if the step was executed and failed
+    pub fn error(self) -> BatchCond {
+        BatchCond::Error { step: self.0 }
+    }
+}
+
+/// A batch of statements, each one executed only if its condition holds.
+/// See the [module documentation](self).
+#[derive(Default)]
+pub struct BatchBuilder {
+    steps: Vec<(Option<BatchCond>, Statement)>,
+}
+
+impl BatchBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a step which is always executed
+    pub fn step(&mut self, stmt: impl Into<Statement>) -> Step {
+        self.push(None, stmt.into())
+    }
+
+    /// Adds a step which is only executed if `cond` holds. Conditions can only refer
+    /// to the steps added before, so they are evaluated once those have run.
+    pub fn step_if(&mut self, cond: BatchCond, stmt: impl Into<Statement>) -> Step {
+        self.push(Some(cond), stmt.into())
+    }
+
+    /// Number of steps in the batch
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    fn push(&mut self, cond: Option<BatchCond>, stmt: Statement) -> Step {
+        self.steps.push((cond, stmt));
+        Step(self.steps.len() as u32 - 1)
+    }
+
+    /// True if every step is executed unconditionally.
+    #[cfg(feature = "hrana_backend")]
+    pub(crate) fn is_unconditional(&self) -> bool {
+        self.steps.iter().all(|(cond, _)| cond.is_none())
+    }
+
+    pub(crate) fn into_steps(self) -> Vec<(Option<BatchCond>, Statement)> {
+        self.steps
+    }
+}
+
+/// Emulates a batch on the client, by executing its steps one at a time
+/// and evaluating their conditions along the way.
+#[cfg(any(feature = "local_backend", feature = "hrana_backend"))]
+#[derive(Default)]
+pub(crate) struct Emulation {
+    outcomes: Vec<Option<bool>>,
+    result: BatchResult,
+}
+
+#[cfg(any(feature = "local_backend", feature = "hrana_backend"))]
+impl Emulation {
+    /// True if a step with condition `cond` should be executed
+    pub(crate) fn should_run(&self, cond: Option<&BatchCond>) -> bool {
+        match cond {
+            Some(cond) => cond.eval(&self.outcomes),
+            None => true,
+        }
+    }
+
+    /// Records the outcome of a step, `None` if it was skipped
+    pub(crate) fn record(&mut self, outcome: Option<crate::Result<StmtResult>>) {
+        let (result, error, outcome) = match outcome {
+            Some(Ok(result)) => (Some(result), None, Some(true)),
+            Some(Err(e)) => (None, Some(step_error(e)), Some(false)),
+            None => (None, None, None),
+        };
+        self.outcomes.push(outcome);
+        self.result.step_results.push(result);
+        self.result.step_errors.push(error);
+    }
+
+    pub(crate) fn finish(self) -> BatchResult {
+        self.result
+    }
+}
+
+/// Converts the error of a step to its representation in a [`BatchResult`].
+#[cfg(any(feature = "local_backend", feature = "hrana_backend"))]
+pub(crate) fn step_error(e: Error) -> proto::Error {
+    proto::Error {
+        code: e
+            .sqlite_code()
+            .and_then(crate::error::codes::name)
+            .map(String::from),
+        message: match e {
+            Error::Sqlite { message, .. } => message,
+            e => e.to_string(),
+        },
+    }
+}
+
+#[cfg(all(test, any(feature = "local_backend", feature = "hrana_backend")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        let mut batch = BatchBuilder::new();
+        let first = batch.step("SELECT 1");
+        let second = batch.step_if(first.ok(), "SELECT 2");
+        assert_eq!((second.index(), batch.len()), (1, 2));
+
+        let outcomes = [Some(true), Some(false), None];
+        assert!(first.ok().eval(&outcomes));
+        assert!(second.error().eval(&outcomes));
+        assert!(!Step(2).ok().eval(&outcomes) && !Step(2).error().eval(&outcomes));
+        assert!(first.ok().and(!second.ok()).eval(&outcomes));
+        assert!(!first.error().or(Step(2).ok()).eval(&outcomes));
+        assert!(!Step(3).ok().eval(&outcomes));
+
+        let json = serde_json::to_value(!first.ok().and(second.error())).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"type": "not", "cond": {"type": "and", "conds": [
+                {"type": "ok", "step": 0},
+                {"type": "error", "step": 1},
+            ]}})
+        );
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod local_tests {
+    use super::*;
+    use crate::{args, Client};
+    use futures::executor::block_on;
+
+    fn upsert(name: &str) -> BatchBuilder {
+        let mut batch = BatchBuilder::new();
+        let insert = batch.step(Statement::with_args(
+            "INSERT INTO counters VALUES (?, 1)",
+            args![name],
+        ));
+        let update = batch.step_if(
+            insert.error(),
+            Statement::with_args(
+                "UPDATE counters SET value = value + 1 WHERE name = ?",
+                args![name],
+            ),
+        );
+        batch.step_if(insert.ok().or(update.ok()), "SELECT 1");
+        batch
+    }
+
+    #[test]
+    fn conditional_steps() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE counters(name TEXT PRIMARY KEY, value INTEGER)"))
+            .unwrap();
+
+        let result = block_on(db.execute_batch(upsert("a"))).unwrap();
+        assert!(result.step_results[0].is_some() && result.step_errors[0].is_none());
+        assert!(result.step_results[1].is_none() && result.step_errors[1].is_none());
+        assert!(result.step_results[2].is_some());
+
+        let result = block_on(db.execute_batch(upsert("a"))).unwrap();
+        let error = result.step_errors[0].as_ref().unwrap();
+        assert_eq!(error.code.as_deref(), Some("SQLITE_CONSTRAINT_PRIMARYKEY"));
+        assert!(result.step_results[1].is_some() && result.step_results[2].is_some());
+
+        let value: Vec<(i64,)> = block_on(db.execute("SELECT value FROM counters"))
+            .unwrap()
+            .into_typed()
+            .unwrap();
+        assert_eq!(value, [(2,)]);
+    }
+}
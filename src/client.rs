@@ -1,7 +1,89 @@
 //! [Client] is the main structure to interact with the database.
-use anyhow::Result;
+use crate::{
+    BatchBuilder, BatchResult, Error, OwnedTransaction, PreparedStatement, Result, ResultSet,
+    RowStream, Statement, SyncOwnedTransaction, SyncTransaction, Transaction, TransactionBehavior,
+};
+use futures::future::BoxFuture;
+use std::sync::Arc;
 
-use crate::{proto, BatchResult, ResultSet, Statement, SyncTransaction, Transaction};
+/// True if a transaction failed because of another connection or a lost stream,
+/// so that running it again may succeed.
+///
+/// This also holds when COMMIT failed: backends only report [`Error::StreamExpired`] for it
+/// if the server rejected the stream or the stream was lost before COMMIT was sent.
+/// A connection lost while waiting for the answer leaves the outcome of COMMIT unknown,
+/// and is reported as [`Error::Transport`], which is never retried.
+fn is_conflict(e: &Error) -> bool {
+    e.is_busy() || e.is_stream_expired()
+}
+
+/// Converts the results of a `BEGIN`, `stmts`, `END` batch into one [`ResultSet`] per statement.
+/// Steps following `END`, like a `ROLLBACK` executed if it was not, are ignored.
+///
+/// The first failed statement is reported as [`Error::Batch`], with its index in `stmts`
+/// and its SQL text taken from `sqls`.
+pub(crate) fn batch_result_sets(
+    batch_results: BatchResult,
+    sqls: Vec<String>,
+) -> Result<Vec<ResultSet>> {
+    let count = sqls.len();
+    let step_error = batch_results
+        .step_errors
+        .into_iter()
+        .take(count + 2)
+        .enumerate()
+        .find_map(|(i, e)| e.map(|e| (i, e)));
+    if let Some((i, error)) = step_error {
+        let step = i.checked_sub(1).filter(|step| *step < count);
+        return Err(match step {
+            Some(step) => Error::Batch {
+                step,
+                sql: sqls.into_iter().nth(step).unwrap_or_default(),
+                source: Box::new(error.into()),
+            },
+            // BEGIN or END failed, i.e. the transaction could not be started or committed
+            None => error.into(),
+        });
+    }
+    batch_results
+        .step_results
+        .into_iter()
+        .skip(1) // BEGIN is not counted in the result
+        .take(count)
+        .map(|maybe_rs| {
+            maybe_rs
+                .map(ResultSet::from)
+                .ok_or_else(|| Error::Protocol("Unexpected missing result set".into()))
+        })
+        .collect()
+}
+
+/// Converts the results of a batch into one [`ResultSet`] per statement.
+///
+/// The first failed statement is reported as [`Error::Batch`], with its SQL text taken from `sqls`.
+fn step_result_sets(batch_results: BatchResult, sqls: Vec<String>) -> Result<Vec<ResultSet>> {
+    let step_error = batch_results
+        .step_errors
+        .into_iter()
+        .enumerate()
+        .find_map(|(i, e)| e.map(|e| (i, e)));
+    if let Some((step, error)) = step_error {
+        return Err(Error::Batch {
+            step,
+            sql: sqls.into_iter().nth(step).unwrap_or_default(),
+            source: Box::new(error.into()),
+        });
+    }
+    batch_results
+        .step_results
+        .into_iter()
+        .map(|maybe_rs| {
+            maybe_rs
+                .map(ResultSet::from)
+                .ok_or_else(|| Error::Protocol("Unexpected missing result set".into()))
+        })
+        .collect()
+}
 
 static TRANSACTION_IDS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
 
@@ -9,6 +91,7 @@ static TRANSACTION_IDS: std::sync::atomic::AtomicU64 = std::sync::atomic::Atomic
 /// It's a convenience struct which allows implementing connect()
 /// with backends being passed as env parameters.
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Client {
     #[cfg(feature = "local_backend")]
     Local(crate::local::Client),
@@ -26,7 +109,7 @@ pub enum Client {
 /// A synchronous flavor of [Client]. All its public methods are synchronous,
 /// to make it usable in environments that don't support async/await.
 pub struct SyncClient {
-    inner: Client,
+    pub(crate) inner: Client,
 }
 
 unsafe impl Send for Client {}
@@ -35,6 +118,12 @@ impl Client {
     /// Executes a batch of independent SQL statements.
     ///
     /// For a version in which statements execute transactionally, see [`Client::batch()`]
+    ///
+    /// With the local backend, the batch stops at the first failed statement, and the
+    /// statements following it have neither a result nor an error. Remote servers execute
+    /// every statement whatever the outcome of the previous ones, as does
+    /// [`Client::execute_batch()`] with every backend.
+    ///
     /// # Arguments
     /// * `stmts` - SQL statements
     ///
@@ -70,9 +159,53 @@ impl Client {
         }
     }
 
+    /// Executes a batch whose steps only run if their conditions on the outcome
+    /// of previous steps hold, see [`BatchBuilder`].
+    ///
+    /// Like with [`Client::raw_batch()`], steps are not executed transactionally.
+    /// A failed step does not stop the batch: the steps following it run if their
+    /// conditions hold, with every backend.
+    /// Skipped steps have neither a result nor an error in the returned [`BatchResult`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() -> libsql_client::Result<()> {
+    /// use libsql_client::BatchBuilder;
+    ///
+    /// let db = libsql_client::Client::in_memory()?;
+    /// let mut batch = BatchBuilder::new();
+    /// let create = batch.step("create table foo(bar text)");
+    /// batch.step_if(create.ok(), "insert into foo(bar) values ('bar')");
+    /// batch.step_if(create.error(), "select 'foo already exists'");
+    /// let res = db.execute_batch(batch).await?;
+    /// assert!(res.step_results[1].is_some() && res.step_results[2].is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn execute_batch(&self, batch: BatchBuilder) -> Result<BatchResult> {
+        match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(l) => l.execute_batch(batch),
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.execute_batch(batch).await,
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.execute_batch(batch).await,
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
     /// Transactionally executes a batch of SQL statements.
     ///
     /// For a version in which statements can fail or succeed independently, see [`Client::raw_batch()`]
+    ///
+    /// If a statement fails, the statements following it are not executed and the
+    /// transaction is rolled back, with every backend.
+    ///
     /// # Arguments
     /// * `stmts` - SQL statements
     ///
@@ -97,35 +230,54 @@ impl Client {
     where
         <I as IntoIterator>::IntoIter: Send,
     {
-        let batch_results = self
-            .raw_batch(
-                std::iter::once(Statement::new("BEGIN"))
-                    .chain(stmts.into_iter().map(|s| s.into()))
-                    .chain(std::iter::once(Statement::new("END"))),
-            )
-            .await?;
-        let step_error: Option<proto::Error> = batch_results
-            .step_errors
-            .into_iter()
-            .skip(1)
-            .find(|e| e.is_some())
-            .flatten();
-        if let Some(error) = step_error {
-            return Err(anyhow::anyhow!(error.message));
+        #[cfg(feature = "local_backend")]
+        if let Self::Local(l) = self {
+            // The local backend needs to roll back on its own if the batch fails
+            return l.batch(stmts);
+        }
+        let stmts: Vec<Statement> = stmts.into_iter().map(|s| s.into()).collect();
+        let sqls = stmts.iter().map(|s| s.sql.clone()).collect();
+        // The server runs every step of a batch, so each one is conditioned on the success
+        // of the previous one, and the transaction is rolled back unless END succeeded
+        let mut batch = BatchBuilder::new();
+        let mut last = batch.step("BEGIN");
+        for stmt in stmts {
+            last = batch.step_if(last.ok(), stmt);
         }
-        let mut step_results: Vec<Result<ResultSet>> = batch_results
-            .step_results
-            .into_iter()
-            .skip(1) // BEGIN is not counted in the result, it's implicitly ignored
-            .map(|maybe_rs| {
-                maybe_rs
-                    .map(ResultSet::from)
-                    .ok_or_else(|| anyhow::anyhow!("Unexpected missing result set"))
-            })
-            .collect();
-        step_results.pop(); // END is not counted in the result, it's implicitly ignored
-                            // Collect all the results into a single Result
-        step_results.into_iter().collect::<Result<Vec<ResultSet>>>()
+        let end = batch.step_if(last.ok(), "END");
+        batch.step_if(!end.ok(), "ROLLBACK");
+        let batch_results = self.execute_batch(batch).await?;
+        batch_result_sets(batch_results, sqls)
+    }
+
+    /// Transactionally executes a script made of several SQL statements separated by
+    /// semicolons, such as a schema file or fixtures, see [`Client::batch()`].
+    ///
+    /// The script is split into its statements on the client, so it must not contain
+    /// transaction control statements like `BEGIN` and `COMMIT`.
+    /// One [ResultSet] is returned per statement of the script.
+    ///
+    /// # Arguments
+    /// * `sql` - SQL script
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// let res = db
+    ///     .execute_script(
+    ///         "CREATE TABLE foo(bar TEXT);
+    ///          INSERT INTO foo(bar) VALUES ('baz');
+    ///          SELECT * FROM foo;",
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(res[2].rows.len(), 1)
+    /// # }
+    /// ```
+    pub async fn execute_script(&self, sql: &str) -> Result<Vec<ResultSet>> {
+        self.batch(crate::utils::split_statements(sql)?).await
     }
 
     /// Transactionally executes a batch of SQL statements, in synchronous contexts.
@@ -192,6 +344,137 @@ impl Client {
         }
     }
 
+    /// Executes a single SQL statement that must return exactly one row,
+    /// and deserializes it into `T`, see [`ResultSet::into_typed`].
+    ///
+    /// Returns [`Error::NoRows`](crate::Error::NoRows) if there are no rows,
+    /// and [`Error::TooManyRows`](crate::Error::TooManyRows) if there are more than one.
+    ///
+    /// # Arguments
+    /// * `stmt` - SQL statement
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// # db.execute("create table foo(id integer primary key, bar text)").await.unwrap();
+    /// # db.execute("insert into foo values (1, 'one')").await.unwrap();
+    /// let (bar,): (String,) = db
+    ///     .query_one(libsql_client::Statement::with_args("select bar from foo where id = ?", &[1]))
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn query_one<T: serde::de::DeserializeOwned>(
+        &self,
+        stmt: impl Into<Statement> + Send,
+    ) -> Result<T> {
+        self.query_optional(stmt).await?.ok_or(crate::Error::NoRows)
+    }
+
+    /// Executes a single SQL statement that must return at most one row,
+    /// and deserializes it into `T`, see [`ResultSet::into_typed`].
+    ///
+    /// Returns [`Error::TooManyRows`](crate::Error::TooManyRows) if there are more than one.
+    ///
+    /// # Arguments
+    /// * `stmt` - SQL statement
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// # db.execute("create table foo(id integer primary key, bar text)").await.unwrap();
+    /// let bar: Option<(String,)> = db
+    ///     .query_optional(libsql_client::Statement::with_args("select bar from foo where id = ?", &[1]))
+    ///     .await
+    ///     .unwrap();
+    /// assert!(bar.is_none());
+    /// # }
+    /// ```
+    pub async fn query_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        stmt: impl Into<Statement> + Send,
+    ) -> Result<Option<T>> {
+        self.execute(stmt).await?.optional()
+    }
+
+    /// Prepares a statement to be executed many times with different arguments,
+    /// see [PreparedStatement].
+    ///
+    /// # Arguments
+    /// * `sql` - SQL text of the statement, possibly with parameter placeholders
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// # db.execute("create table foo(bar text)").await.unwrap();
+    /// let insert = db.prepare("insert into foo(bar) values (?)").await.unwrap();
+    /// db.execute(insert.bind(&["one"])).await.unwrap();
+    /// db.execute(insert.bind(&["two"])).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn prepare(&self, sql: impl Into<String> + Send) -> Result<PreparedStatement> {
+        match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(l) => l.prepare(sql),
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.prepare(sql),
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.prepare(sql),
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
+    /// Executes a single SQL statement and returns its rows as a stream, so that
+    /// large results do not have to fit in memory at once.
+    ///
+    /// Rows are stepped through one at a time by the local backend, and received
+    /// through a cursor by the HTTP backends. The hrana (WebSocket) backend has no
+    /// cursors and receives all the rows before yielding them, and so do the HTTP backends
+    /// with servers that do not support version 3 of the Hrana protocol.
+    ///
+    /// # Arguments
+    /// * `stmt` - SQL statement
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// use futures::TryStreamExt;
+    ///
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// # db.execute("create table foo(bar text)").await.unwrap();
+    /// let mut rows = db.query_stream("select * from foo").await.unwrap();
+    /// while let Some(row) = rows.try_next().await.unwrap() {
+    ///     println!("{:?}", row.values);
+    /// }
+    /// # }
+    /// ```
+    pub async fn query_stream(&self, stmt: impl Into<Statement> + Send) -> Result<RowStream> {
+        match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(l) => l.query_stream(stmt),
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.query_stream(stmt).await,
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.query_stream(stmt).await,
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
     /// Creates an interactive transaction
     ///
     /// # Examples
@@ -206,11 +489,176 @@ impl Client {
     /// tx.commit();
     /// # }
     /// ```
-    pub async fn transaction(&self) -> Result<Transaction> {
+    pub async fn transaction(&self) -> Result<Transaction<'_>> {
         let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         Transaction::new(self, id).await
     }
 
+    /// Creates an interactive transaction with the given behavior
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// use libsql_client::TransactionBehavior;
+    ///
+    /// let db = libsql_client::Client::in_memory().unwrap();
+    /// # db.execute("create table foo(bar text)").await.unwrap();
+    /// let tx = db.transaction_with(TransactionBehavior::ReadOnly).await.unwrap();
+    /// tx.execute("select * from foo").await.unwrap();
+    /// assert!(tx.execute("delete from foo").await.unwrap_err().is_readonly());
+    /// tx.commit().await.unwrap();
+    /// # }
+    /// ```
+    pub async fn transaction_with(&self, behavior: TransactionBehavior) -> Result<Transaction<'_>> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Transaction::with_behavior(self, id, behavior).await
+    }
+
+    /// Runs `f` in an interactive transaction, which is committed if `f` succeeds
+    /// and rolled back if it fails.
+    ///
+    /// If the database was busy, or the stream of the transaction was lost, the whole
+    /// transaction is retried according to [`RetryPolicy::default()`].
+    /// See [`Client::with_transaction_retry`] to retry with another policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() -> libsql_client::Result<()> {
+    /// let db = libsql_client::Client::in_memory()?;
+    /// # db.execute("create table accounts(id integer primary key, balance integer)").await?;
+    /// let balance: i64 = db
+    ///     .with_transaction(|tx| {
+    ///         Box::pin(async move {
+    ///             tx.execute("update accounts set balance = balance - 10 where id = 1").await?;
+    ///             tx.execute("update accounts set balance = balance + 10 where id = 2").await?;
+    ///             tx.execute("select sum(balance) from accounts").await?.scalar()
+    ///         })
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_transaction<T, F>(&self, f: F) -> Result<T>
+    where
+        F: for<'t> FnMut(&'t Transaction<'_>) -> BoxFuture<'t, Result<T>>,
+    {
+        self.with_transaction_retry(&RetryPolicy::default(), f)
+            .await
+    }
+
+    /// Like [`Client::with_transaction`], retrying the transaction according to `retry`.
+    /// [`RetryPolicy::disabled()`] runs it only once.
+    pub async fn with_transaction_retry<T, F>(&self, retry: &RetryPolicy, mut f: F) -> Result<T>
+    where
+        F: for<'t> FnMut(&'t Transaction<'_>) -> BoxFuture<'t, Result<T>>,
+    {
+        let mut delay = retry.backoff.min(retry.max_backoff);
+        let mut attempt = 1;
+        loop {
+            let result = async {
+                let tx = self.transaction().await?;
+                match f(&tx).await {
+                    Ok(value) => tx.commit().await.map(|()| value),
+                    Err(e) => {
+                        tx.rollback().await.ok();
+                        Err(e)
+                    }
+                }
+            }
+            .await;
+            match result {
+                Err(e) if attempt < retry.max_attempts && is_conflict(&e) && self.can_sleep() => {
+                    let wait = retry.wait(delay);
+                    tracing::warn!(
+                        "Transaction failed (attempt {attempt}), retrying in {wait:?}: {e}"
+                    );
+                    self.sleep(wait).await;
+                    delay = (delay * 2).min(retry.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Creates an interactive transaction owning a handle to the client,
+    /// which can be moved into spawned tasks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = std::sync::Arc::new(libsql_client::Client::in_memory().unwrap());
+    /// # db.execute("create table foo(bar text)").await.unwrap();
+    /// let tx = db.clone().owned_transaction().await.unwrap();
+    /// tokio::spawn(async move {
+    ///     tx.execute("insert into foo values ('baz')").await.unwrap();
+    ///     tx.commit().await.unwrap();
+    /// })
+    /// .await
+    /// .unwrap();
+    /// # }
+    /// ```
+    pub async fn owned_transaction(self: Arc<Self>) -> Result<OwnedTransaction> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        OwnedTransaction::new(self, id).await
+    }
+
+    /// Creates an interactive transaction owning a handle to the client,
+    /// with the given behavior, see [`Client::owned_transaction`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// use libsql_client::TransactionBehavior;
+    ///
+    /// let db = std::sync::Arc::new(libsql_client::Client::in_memory().unwrap());
+    /// # db.execute("create table foo(bar text)").await.unwrap();
+    /// let tx = db
+    ///     .clone()
+    ///     .owned_transaction_with(TransactionBehavior::Immediate)
+    ///     .await
+    ///     .unwrap();
+    /// tx.execute("insert into foo values ('baz')").await.unwrap();
+    /// tx.commit().await.unwrap();
+    /// # }
+    /// ```
+    pub async fn owned_transaction_with(
+        self: Arc<Self>,
+        behavior: TransactionBehavior,
+    ) -> Result<OwnedTransaction> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        OwnedTransaction::with_behavior(self, id, behavior).await
+    }
+
+    // Routes the transaction `tx_id` to the read replica of the database, if there is one.
+    pub(crate) async fn use_read_replica(&self, tx_id: u64) -> Result<()> {
+        match self {
+            // A local database has no replica
+            #[cfg(feature = "local_backend")]
+            Self::Local(_) => {
+                let _ = tx_id;
+                Ok(())
+            }
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => {
+                r.use_read_replica(tx_id);
+                Ok(())
+            }
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.use_read_replica(tx_id).await,
+
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
     pub(crate) async fn execute_in_transaction(
         &self,
         tx_id: u64,
@@ -232,6 +680,29 @@ impl Client {
         }
     }
 
+    pub(crate) async fn batch_in_transaction(
+        &self,
+        tx_id: u64,
+        stmts: Vec<Statement>,
+    ) -> Result<Vec<ResultSet>> {
+        let sqls = stmts.iter().map(|s| s.sql.clone()).collect();
+        let batch_results = match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(l) => l.batch_in_transaction(tx_id, stmts),
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.batch_in_transaction(tx_id, stmts).await,
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.batch_in_transaction(tx_id, stmts).await,
+
+            _ => panic!("Must enable at least one feature"),
+        }?;
+        step_result_sets(batch_results, sqls)
+    }
+
     pub(crate) async fn commit_transaction(&self, tx_id: u64) -> Result<()> {
         match self {
             #[cfg(feature = "local_backend")]
@@ -265,6 +736,56 @@ impl Client {
             _ => panic!("Must enable at least one feature"),
         }
     }
+
+    /// False if the backend cannot wait before retrying a failed operation
+    /// without blocking, in which case the operation is not retried.
+    pub(crate) fn can_sleep(&self) -> bool {
+        match self {
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.can_sleep(),
+            _ => true,
+        }
+    }
+
+    /// Waits for `duration` before retrying a failed operation.
+    pub(crate) async fn sleep(&self, duration: std::time::Duration) {
+        match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(_) => crate::utils::sleep(duration).await,
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.sleep(duration).await,
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(_) => tokio::time::sleep(duration).await,
+
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
+    pub(crate) fn drop_transaction(&self, tx_id: u64) {
+        tracing::warn!("Transaction {tx_id} dropped without commit or rollback, rolling it back");
+        match self {
+            #[cfg(feature = "local_backend")]
+            Self::Local(l) => l.drop_transaction(tx_id),
+            #[cfg(any(
+                feature = "reqwest_backend",
+                feature = "workers_backend",
+                feature = "spin_backend"
+            ))]
+            Self::Http(r) => r.drop_transaction(tx_id),
+            #[cfg(feature = "hrana_backend")]
+            Self::Hrana(h) => h.drop_transaction(tx_id),
+
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
 }
 
 impl Client {
@@ -279,7 +800,7 @@ impl Client {
     /// # }
     /// ```
     #[cfg(feature = "local_backend")]
-    pub fn in_memory() -> anyhow::Result<Client> {
+    pub fn in_memory() -> Result<Client> {
         Ok(Client::Local(crate::local::Client::in_memory()?))
     }
 
@@ -290,22 +811,14 @@ impl Client {
     /// ```
     /// # async fn f() {
     /// # use libsql_client::Config;
-    /// let config = Config {
-    ///   url: url::Url::parse("file:////tmp/example.db").unwrap(),
-    ///   auth_token: None
-    /// };
+    /// let config = Config::new("file:////tmp/example.db").unwrap();
     /// let db = libsql_client::Client::from_config(config).await.unwrap();
     /// # }
     /// ```
     #[allow(unreachable_patterns)]
-    pub async fn from_config<'a>(mut config: Config) -> anyhow::Result<Client> {
-        config.url = if config.url.scheme() == "libsql" {
-            // We cannot use url::Url::set_scheme() because it prevents changing the scheme to http...
-            // Safe to unwrap, because we know that the scheme is libsql
-            url::Url::parse(&config.url.as_str().replace("libsql://", "https://")).unwrap()
-        } else {
-            config.url
-        };
+    pub async fn from_config(mut config: Config) -> Result<Client> {
+        config.url = from_libsql(config.url);
+        config.read_replica = config.read_replica.map(from_libsql);
         let scheme = config.url.scheme();
         Ok(match scheme {
             #[cfg(feature = "local_backend")]
@@ -318,20 +831,20 @@ impl Client {
             },
             #[cfg(feature = "reqwest_backend")]
             "http" | "https" => {
-                let inner = crate::http::InnerClient::Reqwest(crate::reqwest::HttpClient::new());
+                let inner = crate::http::InnerClient::Reqwest(crate::reqwest::HttpClient::with_timeouts(&config.timeouts));
                 Client::Http(crate::http::Client::from_config(inner, config)?)
             },
             #[cfg(feature = "workers_backend")]
             "workers" | "http" | "https" => {
-                let inner = crate::http::InnerClient::Workers(crate::workers::HttpClient::new());
+                let inner = crate::http::InnerClient::Workers(crate::workers::HttpClient::with_timeouts(&config.timeouts));
                 Client::Http(crate::http::Client::from_config(inner, config)?)
             },
             #[cfg(feature = "spin_backend")]
             "spin" | "http" | "https" => {
-                let inner = crate::http::InnerClient::Spin(crate::spin::HttpClient::new());
+                let inner = crate::http::InnerClient::Spin(crate::spin::HttpClient::with_timeouts(&config.timeouts));
                 Client::Http(crate::http::Client::from_config(inner, config)?)
             },
-            _ => anyhow::bail!("Unknown scheme: {scheme}. Make sure your backend exists and is enabled with its feature flag"),
+            _ => return Err(Error::Config(format!("Unknown scheme: {scheme}. Make sure your backend exists and is enabled with its feature flag"))),
         })
     }
 
@@ -352,33 +865,31 @@ impl Client {
     /// let db = libsql_client::Client::from_env().await.unwrap();
     /// # }
     /// ```
-    pub async fn from_env() -> anyhow::Result<Client> {
+    pub async fn from_env() -> Result<Client> {
         let url = std::env::var("LIBSQL_CLIENT_URL").map_err(|_| {
-            anyhow::anyhow!("LIBSQL_CLIENT_URL variable should point to your libSQL/sqld database")
+            Error::Config(
+                "LIBSQL_CLIENT_URL variable should point to your libSQL/sqld database".into(),
+            )
         })?;
-        let auth_token = std::env::var("LIBSQL_CLIENT_TOKEN").ok();
-        Self::from_config(Config {
-            url: url::Url::parse(&url)?,
-            auth_token,
-        })
-        .await
+        let mut config = Config::new(url.as_str())?;
+        config.auth_token = std::env::var("LIBSQL_CLIENT_TOKEN").ok();
+        Self::from_config(config).await
     }
 
     #[cfg(feature = "workers_backend")]
-    pub fn from_workers_env(env: &worker::Env) -> anyhow::Result<Client> {
+    pub fn from_workers_env(env: &worker::Env) -> Result<Client> {
         let url = env
             .secret("LIBSQL_CLIENT_URL")
-            .map_err(|e| anyhow::anyhow!("{e}"))?
+            .map_err(|e| Error::Config(e.to_string()))?
             .to_string();
         let token = env
             .secret("LIBSQL_CLIENT_TOKEN")
-            .map_err(|e| anyhow::anyhow!("{e}"))?
+            .map_err(|e| Error::Config(e.to_string()))?
             .to_string();
-        let config = Config {
-            url: url::Url::parse(&url)?,
-            auth_token: Some(token),
-        };
-        let inner = crate::http::InnerClient::Workers(crate::workers::HttpClient::new());
+        let config = Config::new(url.as_str())?.with_auth_token(token);
+        let inner = crate::http::InnerClient::Workers(crate::workers::HttpClient::with_timeouts(
+            &config.timeouts,
+        ));
         Ok(Client::Http(crate::http::Client::from_config(
             inner, config,
         )?))
@@ -398,7 +909,7 @@ impl SyncClient {
     /// # }
     /// ```
     #[cfg(feature = "local_backend")]
-    pub fn in_memory() -> anyhow::Result<Self> {
+    pub fn in_memory() -> Result<Self> {
         Ok(Self {
             inner: Client::in_memory()?,
         })
@@ -411,7 +922,7 @@ impl SyncClient {
     /// ```
     /// # fn f() {
     /// # use libsql_client::Config;
-    /// let config = Config { url: url::Url::parse("file:////tmp/example.db").unwrap(), auth_token: None };
+    /// let config = Config::new("file:////tmp/example.db").unwrap();
     /// let db = libsql_client::SyncClient::from_config(config).unwrap();
     /// # }
     /// ```
@@ -454,6 +965,10 @@ impl SyncClient {
     /// Executes a batch of independent SQL statements.
     ///
     /// For a version in which statements execute transactionally, see [`SyncClient::batch()`]
+    ///
+    /// Whether a failed statement stops the batch depends on the backend,
+    /// see [`Client::raw_batch()`].
+    ///
     /// # Arguments
     /// * `stmts` - SQL statements
     ///
@@ -477,6 +992,28 @@ impl SyncClient {
         futures::executor::block_on(self.inner.raw_batch(stmts))
     }
 
+    /// Executes a batch whose steps only run if their conditions on the outcome
+    /// of previous steps hold, see [`BatchBuilder`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn run() -> libsql_client::Result<()> {
+    /// use libsql_client::BatchBuilder;
+    ///
+    /// let db = libsql_client::SyncClient::in_memory()?;
+    /// let mut batch = BatchBuilder::new();
+    /// let create = batch.step("create table foo(bar text)");
+    /// batch.step_if(create.ok(), "insert into foo(bar) values ('bar')");
+    /// let res = db.execute_batch(batch)?;
+    /// assert!(res.step_results[1].is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute_batch(&self, batch: BatchBuilder) -> Result<BatchResult> {
+        futures::executor::block_on(self.inner.execute_batch(batch))
+    }
+
     /// Transactionally executes a batch of SQL statements.
     ///
     /// For a version in which statements can fail or succeed independently, see [`SyncClient::raw_batch()`]
@@ -526,6 +1063,59 @@ impl SyncClient {
         futures::executor::block_on(self.inner.execute(stmt))
     }
 
+    /// Transactionally executes a script made of several SQL statements,
+    /// see [`Client::execute_script`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// db.execute_script("CREATE TABLE foo(bar TEXT); INSERT INTO foo(bar) VALUES ('baz')")
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub fn execute_script(&self, sql: &str) -> Result<Vec<ResultSet>> {
+        futures::executor::block_on(self.inner.execute_script(sql))
+    }
+
+    /// Executes a single SQL statement that must return exactly one row,
+    /// see [`Client::query_one`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// let (count,): (i64,) = db.query_one("select count(*) from sqlite_schema").unwrap();
+    /// # }
+    /// ```
+    pub fn query_one<T: serde::de::DeserializeOwned>(
+        &self,
+        stmt: impl Into<Statement> + Send,
+    ) -> Result<T> {
+        futures::executor::block_on(self.inner.query_one(stmt))
+    }
+
+    /// Executes a single SQL statement that must return at most one row,
+    /// see [`Client::query_optional`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn run() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// # db.execute("create table foo(bar text)").unwrap();
+    /// let bar: Option<(String,)> = db.query_optional("select bar from foo").unwrap();
+    /// # }
+    /// ```
+    pub fn query_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        stmt: impl Into<Statement> + Send,
+    ) -> Result<Option<T>> {
+        futures::executor::block_on(self.inner.query_optional(stmt))
+    }
+
     /// Creates an interactive transaction
     ///
     /// # Examples
@@ -540,29 +1130,341 @@ impl SyncClient {
     /// tx.commit();
     /// # }
     /// ```
-    pub fn transaction(&self) -> Result<SyncTransaction> {
+    pub fn transaction(&self) -> Result<SyncTransaction<'_>> {
         let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         SyncTransaction::new(self, id)
     }
 
-    pub(crate) fn execute_in_transaction(&self, tx_id: u64, stmt: Statement) -> Result<ResultSet> {
-        futures::executor::block_on(self.inner.execute_in_transaction(tx_id, stmt))
+    /// Creates an interactive transaction with the given behavior
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libsql_client::TransactionBehavior;
+    ///
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// # db.execute("create table foo(bar text)").unwrap();
+    /// let tx = db.transaction_with(TransactionBehavior::Immediate).unwrap();
+    /// tx.execute("insert into foo values ('baz')").unwrap();
+    /// tx.commit().unwrap();
+    /// ```
+    pub fn transaction_with(&self, behavior: TransactionBehavior) -> Result<SyncTransaction<'_>> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        SyncTransaction::with_behavior(self, id, behavior)
     }
 
-    pub(crate) fn commit_transaction(&self, tx_id: u64) -> Result<()> {
-        futures::executor::block_on(self.inner.commit_transaction(tx_id))
+    /// Runs `f` in an interactive transaction, which is committed if `f` succeeds
+    /// and rolled back if it fails.
+    ///
+    /// If the database was busy, or the stream of the transaction was lost, the whole
+    /// transaction is retried according to [`RetryPolicy::default()`].
+    /// See [`SyncClient::with_transaction_retry`] to retry with another policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn run() -> libsql_client::Result<()> {
+    /// let db = libsql_client::SyncClient::in_memory()?;
+    /// # db.execute("create table accounts(id integer primary key, balance integer)")?;
+    /// let balance: i64 = db.with_transaction(|tx| {
+    ///     tx.execute("update accounts set balance = balance - 10 where id = 1")?;
+    ///     tx.execute("update accounts set balance = balance + 10 where id = 2")?;
+    ///     tx.execute("select sum(balance) from accounts")?.scalar()
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_transaction<T>(&self, f: impl FnMut(&SyncTransaction) -> Result<T>) -> Result<T> {
+        self.with_transaction_retry(&RetryPolicy::default(), f)
     }
 
-    pub(crate) fn rollback_transaction(&self, tx_id: u64) -> Result<()> {
-        futures::executor::block_on(self.inner.rollback_transaction(tx_id))
+    /// Like [`SyncClient::with_transaction`], retrying the transaction according to `retry`.
+    /// [`RetryPolicy::disabled()`] runs it only once.
+    pub fn with_transaction_retry<T>(
+        &self,
+        retry: &RetryPolicy,
+        mut f: impl FnMut(&SyncTransaction) -> Result<T>,
+    ) -> Result<T> {
+        let mut delay = retry.backoff.min(retry.max_backoff);
+        let mut attempt = 1;
+        loop {
+            let result = self.transaction().and_then(|tx| match f(&tx) {
+                Ok(value) => tx.commit().map(|()| value),
+                Err(e) => {
+                    tx.rollback().ok();
+                    Err(e)
+                }
+            });
+            match result {
+                Err(e)
+                    if attempt < retry.max_attempts
+                        && is_conflict(&e)
+                        && self.inner.can_sleep() =>
+                {
+                    let wait = retry.wait(delay);
+                    tracing::warn!(
+                        "Transaction failed (attempt {attempt}), retrying in {wait:?}: {e}"
+                    );
+                    std::thread::sleep(wait);
+                    delay = (delay * 2).min(retry.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Creates an interactive transaction owning a handle to the client,
+    /// which can be moved to other threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let db = std::sync::Arc::new(libsql_client::SyncClient::in_memory().unwrap());
+    /// # db.execute("create table foo(bar text)").unwrap();
+    /// let tx = db.clone().owned_transaction().unwrap();
+    /// std::thread::spawn(move || {
+    ///     tx.execute("insert into foo values ('baz')").unwrap();
+    ///     tx.commit().unwrap();
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// ```
+    pub fn owned_transaction(self: Arc<Self>) -> Result<SyncOwnedTransaction> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        SyncOwnedTransaction::new(self, id)
+    }
+
+    /// Creates an interactive transaction owning a handle to the client,
+    /// with the given behavior, see [`SyncClient::owned_transaction`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use libsql_client::TransactionBehavior;
+    ///
+    /// let db = std::sync::Arc::new(libsql_client::SyncClient::in_memory().unwrap());
+    /// # db.execute("create table foo(bar text)").unwrap();
+    /// let tx = db
+    ///     .clone()
+    ///     .owned_transaction_with(TransactionBehavior::Immediate)
+    ///     .unwrap();
+    /// tx.execute("insert into foo values ('baz')").unwrap();
+    /// tx.commit().unwrap();
+    /// ```
+    pub fn owned_transaction_with(
+        self: Arc<Self>,
+        behavior: TransactionBehavior,
+    ) -> Result<SyncOwnedTransaction> {
+        let id = TRANSACTION_IDS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        SyncOwnedTransaction::with_behavior(self, id, behavior)
+    }
+}
+
+// Replaces the libsql:// scheme of a URL with https://, which sqld serves it with.
+fn from_libsql(url: url::Url) -> url::Url {
+    if url.scheme() == "libsql" {
+        // We cannot use url::Url::set_scheme() because it prevents changing the scheme to http...
+        // Safe to unwrap, because we know that the scheme is libsql
+        url::Url::parse(&url.as_str().replace("libsql://", "https://")).unwrap()
+    } else {
+        url
     }
 }
 
 /// Configuration for the database client
+///
+/// Besides [Config::new] and its `with_*` methods, a config can be built as a struct
+/// literal, with the settings left out taken from [Config::default]:
+///
+/// ```
+/// # fn f() -> anyhow::Result<()> {
+/// # use libsql_client::Config;
+/// let config = Config {
+///     url: url::Url::parse("libsql://localhost:8080")?,
+///     auth_token: None,
+///     ..Default::default()
+/// };
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Debug)]
 pub struct Config {
     pub url: url::Url,
     pub auth_token: Option<String>,
+    /// Connection pool settings, used by the hrana (WebSocket) backend
+    pub pool: PoolConfig,
+    /// Retry settings for requests that are safe to repeat, used by the HTTP backends
+    pub retry: RetryPolicy,
+    /// Timeouts of requests sent by the HTTP backends
+    pub timeouts: Timeouts,
+    /// URL of a read replica of the database on sqld, which serves the transactions
+    /// begun with [`TransactionBehavior::ReadOnly`], used by the remote backends
+    pub read_replica: Option<url::Url>,
+}
+
+/// Configuration of the pool of WebSocket connections used by the hrana backend.
+///
+/// Streams are spread across the pooled connections: a new connection is opened
+/// only when all existing ones are in use and `max_connections` is not reached yet.
+///
+/// # Examples
+///
+/// ```
+/// # fn f() -> anyhow::Result<()> {
+/// # use libsql_client::{Config, PoolConfig};
+/// let config = Config::new("wss://example.com/db")?.with_pool(PoolConfig {
+///     min_connections: 2,
+///     max_connections: 8,
+///     ..Default::default()
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Number of connections opened eagerly and kept open even when idle
+    pub min_connections: usize,
+    /// Maximum number of connections open at the same time
+    pub max_connections: usize,
+    /// Connections above `min_connections` that stay unused for longer than this are closed
+    pub idle_timeout: Option<std::time::Duration>,
+    /// A connection that was not verified for longer than this is checked with a round-trip
+    /// to the server before its next use, and evicted if the check fails
+    pub health_check_interval: Option<std::time::Duration>,
+    /// How many times opening a replacement connection is attempted before giving up
+    pub max_reconnect_attempts: u32,
+    /// Delay before the second connection attempt, doubled after every failed attempt
+    pub reconnect_backoff: std::time::Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_connections: 1,
+            max_connections: 1,
+            idle_timeout: Some(std::time::Duration::from_secs(300)),
+            health_check_interval: None,
+            max_reconnect_attempts: 5,
+            reconnect_backoff: std::time::Duration::from_millis(100),
+        }
+    }
+}
+
+/// Policy for retrying requests that failed with a transient error, used by the HTTP backends,
+/// and for retrying transactions run by [`Client::with_transaction`].
+///
+/// Only requests that are safe to repeat are retried: statements detected as read-only,
+/// and statements explicitly marked with [Statement::idempotent]. Statements executed
+/// within an interactive transaction are never retried.
+///
+/// The Spin backend never retries, since it cannot wait between attempts without stalling
+/// the component: the error of the first attempt is returned, for the caller to retry.
+///
+/// # Examples
+///
+/// ```
+/// # fn f() -> anyhow::Result<()> {
+/// # use libsql_client::{Config, RetryPolicy};
+/// let config = Config::new("https://example.com/db")?.with_retry_policy(RetryPolicy {
+///     max_attempts: 5,
+///     ..Default::default()
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How many times a request is sent before giving up, 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the second attempt, doubled after every failed attempt
+    pub backoff: std::time::Duration,
+    /// Upper bound of the delay between two attempts
+    pub max_backoff: std::time::Duration,
+    /// If set, each delay is randomized to between half and all of its value,
+    /// so that clients which failed at the same time do not retry in lockstep
+    pub jitter: bool,
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request exactly once
+    pub fn disabled() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// How long to wait before the next attempt, given the current `delay`.
+    pub(crate) fn wait(&self, delay: std::time::Duration) -> std::time::Duration {
+        if self.jitter {
+            crate::utils::with_jitter(delay)
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: std::time::Duration::from_millis(100),
+            max_backoff: std::time::Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+}
+
+/// Timeouts of requests sent by the HTTP backends. `None` means no limit.
+///
+/// # Examples
+///
+/// ```
+/// # fn f() -> anyhow::Result<()> {
+/// # use libsql_client::{Config, Timeouts};
+/// # use std::time::Duration;
+/// let config = Config::new("https://example.com/db")?.with_timeouts(Timeouts {
+///     statement: Some(Duration::from_secs(10)),
+///     ..Default::default()
+/// });
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Timeouts {
+    /// Maximum time to establish a connection to the server. Not supported by the
+    /// workers backend, where it is covered by the `request` timeout instead
+    pub connect: Option<std::time::Duration>,
+    /// Maximum time of a single HTTP request, from sending it to receiving the whole response
+    pub request: Option<std::time::Duration>,
+    /// Maximum time of executing a statement or a batch, including all retries
+    pub statement: Option<std::time::Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(std::time::Duration::from_secs(10)),
+            request: Some(std::time::Duration::from_secs(30)),
+            statement: None,
+        }
+    }
+}
+
+/// The default config points to a sqld server running locally on its default port,
+/// `http://127.0.0.1:8080`, and uses the default settings.
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: url::Url::parse("http://127.0.0.1:8080").unwrap(), //NOTICE: safe to unwrap, the URL is valid
+            auth_token: None,
+            pool: PoolConfig::default(),
+            retry: RetryPolicy::default(),
+            timeouts: Timeouts::default(),
+            read_replica: None,
+        }
+    }
 }
 
 impl Config {
@@ -584,8 +1486,8 @@ impl Config {
         Ok(Self {
             url: url
                 .try_into()
-                .map_err(|e| anyhow::anyhow!("Failed to parse url: {}", e))?,
-            auth_token: None,
+                .map_err(|e| Error::Config(format!("Failed to parse url: {e}")))?,
+            ..Default::default()
         })
     }
 
@@ -604,4 +1506,212 @@ impl Config {
         self.auth_token = Some(token.into());
         self
     }
+
+    /// Sets the connection pool configuration, see [PoolConfig]
+    pub fn with_pool(mut self, pool: PoolConfig) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Sets the retry policy, see [RetryPolicy]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the timeouts of HTTP requests, see [Timeouts]
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Sets the URL of a read replica of the database, which serves read-only transactions,
+    /// see [`TransactionBehavior::ReadOnly`]. It uses the same scheme and auth token as
+    /// the database URL.
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f() -> anyhow::Result<()> {
+    /// # use libsql_client::Config;
+    /// let config = Config::new("https://example.com/db")?
+    ///     .with_read_replica("https://replica.example.com/db")?;
+    /// let db = libsql_client::Client::from_config(config).await.unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_read_replica<T: TryInto<url::Url>>(mut self, url: T) -> Result<Self>
+    where
+        <T as TryInto<url::Url>>::Error: std::fmt::Display,
+    {
+        self.read_replica = Some(
+            url.try_into()
+                .map_err(|e| Error::Config(format!("Failed to parse url: {e}")))?,
+        );
+        Ok(self)
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn config_literal_with_defaults() {
+        let path = std::env::temp_dir().join(format!("libsql_config_{}.db", std::process::id()));
+        let config = Config {
+            url: url::Url::from_file_path(&path).unwrap(),
+            auth_token: None,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.pool.max_connections,
+            PoolConfig::default().max_connections
+        );
+        assert_eq!(Config::default().url.as_str(), "http://127.0.0.1:8080/");
+
+        let db = block_on(Client::from_config(config)).unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Client with a table holding `count` rows
+    fn numbers(count: i64) -> Client {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x INTEGER)")).unwrap();
+        for x in 0..count {
+            block_on(db.execute(Statement::with_args("INSERT INTO t VALUES (?)", &[x]))).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn first_and_scalar() {
+        let rs = block_on(numbers(0).execute("SELECT x FROM t")).unwrap();
+        assert!(matches!(rs.first::<(i64,)>(), Err(Error::NoRows)));
+        assert!(matches!(rs.scalar::<i64>(), Err(Error::NoRows)));
+
+        let rs = block_on(numbers(1).execute("SELECT x FROM t")).unwrap();
+        assert_eq!(rs.first::<(i64,)>().unwrap(), (0,));
+        assert_eq!(rs.scalar::<i64>().unwrap(), 0);
+
+        let rs = block_on(numbers(3).execute("SELECT x FROM t ORDER BY x DESC")).unwrap();
+        assert_eq!(rs.first::<(i64,)>().unwrap(), (2,));
+        assert_eq!(rs.scalar::<i64>().unwrap(), 2);
+    }
+
+    #[test]
+    fn optional_row() {
+        let rs = block_on(numbers(0).execute("SELECT x FROM t")).unwrap();
+        assert_eq!(rs.optional::<(i64,)>().unwrap(), None);
+        let rs = block_on(numbers(1).execute("SELECT x FROM t")).unwrap();
+        assert_eq!(rs.optional::<(i64,)>().unwrap(), Some((0,)));
+        let rs = block_on(numbers(3).execute("SELECT x FROM t")).unwrap();
+        assert!(matches!(
+            rs.optional::<(i64,)>(),
+            Err(Error::TooManyRows(3))
+        ));
+    }
+
+    #[test]
+    fn query_one_and_optional() {
+        let db = numbers(0);
+        let one = block_on(db.query_one::<(i64,)>("SELECT x FROM t"));
+        assert!(matches!(one, Err(Error::NoRows)));
+        let optional = block_on(db.query_optional::<(i64,)>("SELECT x FROM t"));
+        assert_eq!(optional.unwrap(), None);
+
+        let db = numbers(1);
+        let one = block_on(db.query_one::<(i64,)>("SELECT x FROM t"));
+        assert_eq!(one.unwrap(), (0,));
+        let optional = block_on(db.query_optional::<(i64,)>("SELECT x FROM t"));
+        assert_eq!(optional.unwrap(), Some((0,)));
+
+        let db = numbers(2);
+        let one = block_on(db.query_one::<(i64,)>("SELECT x FROM t"));
+        assert!(matches!(one, Err(Error::TooManyRows(2))));
+        let optional = block_on(db.query_optional::<(i64,)>("SELECT x FROM t"));
+        assert!(matches!(optional, Err(Error::TooManyRows(2))));
+    }
+
+    #[test]
+    fn column_info_of_local_tables() {
+        let db = numbers(1);
+        let rs = block_on(db.execute("SELECT x, x AS y, x + 1 AS z FROM t")).unwrap();
+        let info = rs.column_info();
+        assert_eq!(info.len(), rs.columns.len());
+        assert_eq!(info[0].table.as_deref(), Some("t"));
+        assert_eq!(info[0].origin.as_deref(), Some("x"));
+        assert_eq!(info[0].decltype.as_deref(), Some("INTEGER"));
+        // Renamed columns keep their origin
+        assert_eq!(info[1].name, "y");
+        assert_eq!(info[1].table.as_deref(), Some("t"));
+        assert_eq!(info[1].origin.as_deref(), Some("x"));
+        // Expressions do not come from a table
+        assert_eq!(info[2].name, "z");
+        assert_eq!(info[2].table, None);
+        assert_eq!(info[2].origin, None);
+        assert_eq!(info[2].decltype, None);
+    }
+
+    #[test]
+    fn execute_script_runs_all_statements() {
+        let db = Client::in_memory().unwrap();
+        let results = block_on(db.execute_script(
+            "-- schema
+            CREATE TABLE t(x INTEGER);
+            CREATE TABLE log(x INTEGER);
+            CREATE TRIGGER t_insert AFTER INSERT ON t BEGIN
+                INSERT INTO log VALUES (new.x);
+            END;
+            INSERT INTO t VALUES (1), (2);;
+            SELECT count(*) FROM log",
+        ))
+        .unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[3].rows_affected, 2);
+        assert_eq!(results[4].scalar::<i64>().unwrap(), 2);
+    }
+
+    // Runs a script failing in the middle, and checks that none of it was persisted.
+    async fn assert_failed_script_rolled_back(db: &Client) {
+        let err = db
+            .execute_script(
+                "CREATE TABLE t(x INTEGER);
+                INSERT INTO t VALUES (1);
+                INSERT INTO missing VALUES (2);
+                INSERT INTO t VALUES (3);",
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.batch_step(), Some(2), "{err}");
+        let tables = db
+            .execute("SELECT name FROM sqlite_master WHERE name = 't'")
+            .await
+            .unwrap();
+        assert!(tables.rows.is_empty());
+    }
+
+    #[test]
+    fn failed_script_rolled_back() {
+        let db = Client::in_memory().unwrap();
+        block_on(assert_failed_script_rolled_back(&db));
+    }
+
+    #[cfg(feature = "reqwest_backend")]
+    #[tokio::test]
+    async fn failed_script_rolled_back_over_http() {
+        let server = crate::http_test_server::TestServer::start_sql().await;
+        let db = Client::Http(server.client());
+        assert_failed_script_rolled_back(&db).await;
+    }
+
+    #[cfg(feature = "hrana_backend")]
+    #[tokio::test]
+    async fn failed_script_rolled_back_over_hrana() {
+        let server = crate::hrana_test_server::TestServer::start().await;
+        let db = Client::Hrana(crate::hrana::Client::new(server.url(), "").await.unwrap());
+        assert_failed_script_rolled_back(&db).await;
+    }
 }
@@ -0,0 +1,239 @@
+//! Dates and times in the formats understood by SQLite, for use with `chrono`.
+//!
+//! SQLite has no dedicated date and time type, values are stored as TEXT
+//! (`YYYY-MM-DD HH:MM:SS.SSS`), as INTEGER Unix timestamps or as REAL Julian day numbers.
+//! This module reads any of them into [`chrono::NaiveDateTime`] or [`chrono::DateTime<Utc>`]
+//! and writes them back as TEXT, through `#[serde(with = "libsql_client::datetime")]`.
+//! Values without an explicit offset are assumed to be in UTC, like in SQLite.
+//!
+//! # Example
+//!
+//! ```
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Event {
+//!     name: String,
+//!     #[serde(with = "libsql_client::datetime")]
+//!     created_at: chrono::DateTime<chrono::Utc>,
+//!     #[serde(with = "libsql_client::datetime::option")]
+//!     finished_at: Option<chrono::NaiveDateTime>,
+//! }
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::de::{Error, Visitor};
+use serde::{Deserializer, Serializer};
+
+/// Unix epoch as a Julian day number
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+
+/// Date and time types that can be stored in SQLite
+pub trait SqliteDateTime: Sized {
+    fn from_naive_utc(value: NaiveDateTime) -> Self;
+    fn to_naive_utc(&self) -> NaiveDateTime;
+}
+
+impl SqliteDateTime for NaiveDateTime {
+    fn from_naive_utc(value: NaiveDateTime) -> Self {
+        value
+    }
+
+    fn to_naive_utc(&self) -> NaiveDateTime {
+        *self
+    }
+}
+
+impl SqliteDateTime for DateTime<Utc> {
+    fn from_naive_utc(value: NaiveDateTime) -> Self {
+        value.and_utc()
+    }
+
+    fn to_naive_utc(&self) -> NaiveDateTime {
+        self.naive_utc()
+    }
+}
+
+/// Serializes a date and time as TEXT, e.g. `2023-04-05 06:07:08.123`
+pub fn serialize<T: SqliteDateTime, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&value.to_naive_utc().format("%Y-%m-%d %H:%M:%S%.f"))
+}
+
+/// Deserializes a date and time from TEXT, an INTEGER Unix timestamp or a REAL Julian day
+pub fn deserialize<'de, T: SqliteDateTime, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer
+        .deserialize_any(DateTimeVisitor)
+        .map(T::from_naive_utc)
+}
+
+/// Same as the parent module, for optional values stored as NULL
+pub mod option {
+    use super::*;
+
+    pub fn serialize<T: SqliteDateTime, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: SqliteDateTime, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        struct OptionVisitor;
+
+        impl<'de> Visitor<'de> for OptionVisitor {
+            type Value = Option<NaiveDateTime>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an optional SQLite date and time")
+            }
+
+            fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                deserializer.deserialize_any(DateTimeVisitor).map(Some)
+            }
+        }
+
+        deserializer
+            .deserialize_option(OptionVisitor)
+            .map(|value| value.map(T::from_naive_utc))
+    }
+}
+
+struct DateTimeVisitor;
+
+impl<'de> Visitor<'de> for DateTimeVisitor {
+    type Value = NaiveDateTime;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an SQLite date and time")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse(value).ok_or_else(|| E::custom(format!("invalid date and time: {value}")))
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<Self::Value, E> {
+        DateTime::from_timestamp(value, 0)
+            .map(|value| value.naive_utc())
+            .ok_or_else(|| E::custom(format!("Unix timestamp out of range: {value}")))
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+        let value = i64::try_from(value)
+            .map_err(|_| E::custom(format!("Unix timestamp out of range: {value}")))?;
+        self.visit_i64(value)
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<Self::Value, E> {
+        let millis = ((value - UNIX_EPOCH_JULIAN_DAY) * 86_400_000.0).round();
+        DateTime::from_timestamp_millis(millis as i64)
+            .filter(|_| millis.is_finite())
+            .map(|value| value.naive_utc())
+            .ok_or_else(|| E::custom(format!("Julian day out of range: {value}")))
+    }
+}
+
+/// Parses the TEXT formats accepted by SQLite date and time functions
+fn parse(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(value) = DateTime::parse_from_rfc3339(value) {
+        return Some(value.naive_utc());
+    }
+    let value = value.strip_suffix(['Z', 'z']).unwrap_or(value);
+    [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Timelike;
+    use serde::de::{value::Error as DeError, IntoDeserializer};
+
+    use super::*;
+
+    fn expected() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, 4, 5)
+            .unwrap()
+            .and_hms_milli_opt(6, 7, 8, 500)
+            .unwrap()
+    }
+
+    #[test]
+    fn from_text() {
+        for text in [
+            "2023-04-05 06:07:08.5",
+            "2023-04-05T06:07:08.500",
+            "2023-04-05T06:07:08.5Z",
+            "2023-04-05 08:07:08.5+02:00",
+        ] {
+            let value: NaiveDateTime =
+                deserialize(IntoDeserializer::<DeError>::into_deserializer(text)).unwrap();
+            assert_eq!(value, expected(), "{text}");
+        }
+
+        let value: DateTime<Utc> =
+            deserialize(IntoDeserializer::<DeError>::into_deserializer("2023-04-05")).unwrap();
+        assert_eq!(
+            value.naive_utc(),
+            expected().date().and_hms_opt(0, 0, 0).unwrap()
+        );
+
+        assert!(
+            deserialize::<NaiveDateTime, _>(IntoDeserializer::<DeError>::into_deserializer(
+                "yesterday"
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn from_numbers() {
+        let unix = expected().and_utc().timestamp();
+        let value: NaiveDateTime =
+            deserialize(IntoDeserializer::<DeError>::into_deserializer(unix)).unwrap();
+        assert_eq!(value, expected().with_nanosecond(0).unwrap());
+
+        let julian =
+            UNIX_EPOCH_JULIAN_DAY + expected().and_utc().timestamp_millis() as f64 / 86_400_000.0;
+        let value: NaiveDateTime =
+            deserialize(IntoDeserializer::<DeError>::into_deserializer(julian)).unwrap();
+        assert_eq!(value, expected());
+    }
+
+    #[test]
+    fn to_text() {
+        let text = serialize(&expected(), serde_json::value::Serializer).unwrap();
+        assert_eq!(text, "2023-04-05 06:07:08.500");
+    }
+}
@@ -1,11 +1,13 @@
 //! libsql deserialization utilities.
 
 use serde::de::{value::Error as DeError, Error};
-use std::collections::hash_map::Iter;
+use std::collections::HashSet;
 
 use hrana_client_proto::Value;
 use serde::{
-    de::{value::SeqDeserializer, IntoDeserializer, MapAccess, Visitor},
+    de::{
+        value::SeqDeserializer, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
     Deserialize, Deserializer,
 };
 
@@ -16,14 +18,27 @@ use crate::Row;
 /// # Types
 ///
 /// Structs must match their field name to the column name but the order does not matter.
-/// There is a limited set of Rust types which are supported and those are:
+/// Tuples, tuple structs and sequences such as `Vec<T>` are filled from the columns in order,
+/// like with [`from_values`].
+/// There is a limited set of Rust types which are supported for each column and those are:
 ///
 /// - String
 /// - Vec<u8>
-/// - i64
-/// - f64
+/// - integers, checked for overflow
+/// - f64, f32
+/// - bool, stored as 0 or 1
 /// - Option<T> (where T is any of the above)
 /// - ()
+/// - newtype structs wrapping any of the above
+/// - unit enum variants, stored as TEXT holding the variant name
+/// - structs, maps, sequences and enums with data, stored as TEXT holding JSON
+///
+/// Other types implementing [`serde::Deserialize`] from strings or integers also work,
+/// for example `uuid::Uuid` with the `uuid` feature. Dates and times in any of the SQLite
+/// formats can be read into `chrono` types with [`datetime`](crate::datetime) and the `chrono` feature.
+///
+/// Columns with the same name, e.g. `a.id` and `b.id` in a join, share a single entry in
+/// [`Row::value_map`], so only one of them is visible here. Use [`from_columns`] to detect them.
 ///
 /// # Example
 ///
@@ -50,13 +65,96 @@ use crate::Row;
 /// # Ok(())
 /// # }
 /// ```
-pub fn from_row<'de, T: Deserialize<'de>>(row: &'de Row) -> anyhow::Result<T> {
-    let de = De { row };
+#[cfg(feature = "mapping_names_to_values_in_rows")]
+pub fn from_row<'de, T: Deserialize<'de>>(row: &'de Row) -> crate::Result<T> {
+    let de = De {
+        row,
+        names: Names::Map,
+    };
     T::deserialize(de).map_err(Into::into)
 }
 
+/// Deserialize from the values of a [`Row`], by position, into a tuple, a tuple struct
+/// or a sequence such as `Vec<T>`.
+///
+/// The supported column types are the same as in [`from_row`], but column names are not used,
+/// so this does not need the `mapping_names_to_values_in_rows` feature.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn run(db: libsql_client::Client) -> anyhow::Result<()> {
+/// use libsql_client::de;
+///
+/// let rs = db.execute("SELECT name, age FROM users").await?;
+/// let users = rs
+///     .rows
+///     .iter()
+///     .map(de::from_values)
+///     .collect::<Result<Vec<(String, i64)>, _>>()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn from_values<'de, T: Deserialize<'de>>(row: &'de Row) -> crate::Result<T> {
+    let de = De {
+        row,
+        names: Names::None,
+    };
+    T::deserialize(de).map_err(Into::into)
+}
+
+/// Deserialize from a [`Row`] into any type `T` that implements [`serde::Deserialize`],
+/// matching struct fields with the given column names, usually [`ResultSet::columns`](crate::ResultSet::columns).
+///
+/// Unlike [`from_row`], this does not need the `mapping_names_to_values_in_rows` feature,
+/// and deserializing a struct fails if two columns have the same name, instead of silently
+/// keeping only one of them. Tuples and sequences are filled by position, like with [`from_values`].
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn run(db: libsql_client::Client) -> anyhow::Result<()> {
+/// use libsql_client::de;
+///
+/// #[derive(Debug, serde::Deserialize)]
+/// struct Order {
+///     order_id: i64,
+///     user_id: i64,
+/// }
+///
+/// let rs = db
+///     .execute("SELECT o.id AS order_id, u.id AS user_id FROM orders o JOIN users u ON o.user = u.id")
+///     .await?;
+/// let orders = rs
+///     .rows
+///     .iter()
+///     .map(|row| de::from_columns(&rs.columns, row))
+///     .collect::<Result<Vec<Order>, _>>()?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn from_columns<'de, T: Deserialize<'de>>(
+    columns: &'de [String],
+    row: &'de Row,
+) -> crate::Result<T> {
+    let de = De {
+        row,
+        names: Names::Columns(columns),
+    };
+    T::deserialize(de).map_err(Into::into)
+}
+
+/// Where the column names of a row come from
+enum Names<'de> {
+    #[cfg(feature = "mapping_names_to_values_in_rows")]
+    Map,
+    Columns(&'de [String]),
+    None,
+}
+
 struct De<'de> {
     row: &'de Row,
+    names: Names<'de>,
 }
 
 impl<'de> Deserializer<'de> for De<'de> {
@@ -66,7 +164,7 @@ impl<'de> Deserializer<'de> for De<'de> {
     where
         V: Visitor<'de>,
     {
-        Err(DeError::custom("Expects a struct"))
+        Err(DeError::custom("Expects a struct, a tuple or a sequence"))
     }
 
     fn deserialize_struct<V>(
@@ -78,12 +176,15 @@ impl<'de> Deserializer<'de> for De<'de> {
     where
         V: Visitor<'de>,
     {
-        struct RowMapAccess<'a> {
-            iter: Iter<'a, String, Value>,
+        struct RowMapAccess<'a, I> {
+            iter: I,
             value: Option<&'a Value>,
         }
 
-        impl<'de> MapAccess<'de> for RowMapAccess<'de> {
+        impl<'de, I> MapAccess<'de> for RowMapAccess<'de, I>
+        where
+            I: Iterator<Item = (&'de String, &'de Value)>,
+        {
             type Error = serde::de::value::Error;
 
             fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
@@ -112,21 +213,153 @@ impl<'de> Deserializer<'de> for De<'de> {
             }
         }
 
-        visitor.visit_map(RowMapAccess {
-            iter: self.row.value_map.iter(),
-            value: None,
-        })
+        match self.names {
+            #[cfg(feature = "mapping_names_to_values_in_rows")]
+            Names::Map => visitor.visit_map(RowMapAccess {
+                iter: self.row.value_map.iter(),
+                value: None,
+            }),
+            Names::Columns(columns) => {
+                if columns.len() != self.row.values.len() {
+                    return Err(DeError::custom(format!(
+                        "Expected {} columns, got {} values",
+                        columns.len(),
+                        self.row.values.len()
+                    )));
+                }
+                let mut seen = HashSet::new();
+                if let Some(column) = columns.iter().find(|c| !seen.insert(c.as_str())) {
+                    return Err(DeError::custom(format!("Duplicate column name: {column}")));
+                }
+                visitor.visit_map(RowMapAccess {
+                    iter: columns.iter().zip(self.row.values.iter()),
+                    value: None,
+                })
+            }
+            Names::None => Err(DeError::custom(
+                "Expects a tuple or a sequence, structs need column names",
+            )),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        struct RowSeqAccess<'a> {
+            iter: std::slice::Iter<'a, Value>,
+        }
+
+        impl<'de> SeqAccess<'de> for RowSeqAccess<'de> {
+            type Error = serde::de::value::Error;
+
+            fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+            where
+                T: DeserializeSeed<'de>,
+            {
+                self.iter
+                    .next()
+                    .map(|value| seed.deserialize(V(value)))
+                    .transpose()
+            }
+
+            fn size_hint(&self) -> Option<usize> {
+                Some(self.iter.len())
+            }
+        }
+
+        let mut seq = RowSeqAccess {
+            iter: self.row.values.iter(),
+        };
+        let value = visitor.visit_seq(&mut seq)?;
+        match seq.iter.len() {
+            0 => Ok(value),
+            remaining => Err(DeError::invalid_length(
+                self.row.values.len(),
+                &format!("{} columns", self.row.values.len() - remaining).as_str(),
+            )),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_struct("", &[], visitor)
     }
 
     serde::forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map enum identifier ignored_any
+        bytes byte_buf option unit unit_struct enum identifier ignored_any
     }
 }
 
 struct V<'a>(&'a Value);
 
+macro_rules! deserialize_int {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.0 {
+                    Value::Integer { value } => {
+                        let value = <$ty>::try_from(*value).map_err(|_| {
+                            DeError::custom(format!(
+                                "{value} is out of range for {}",
+                                stringify!($ty)
+                            ))
+                        })?;
+                        visitor.$visit(value)
+                    }
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'a> V<'a> {
+    /// Deserializes a TEXT value holding JSON, for nested structs, maps and sequences.
+    fn json(&self) -> Option<serde_json::Deserializer<serde_json::de::StrRead<'a>>> {
+        match self.0 {
+            Value::Text { value } => Some(serde_json::Deserializer::from_str(value)),
+            _ => None,
+        }
+    }
+}
+
 impl<'de> Deserializer<'de> for V<'de> {
     type Error = serde::de::value::Error;
 
@@ -153,21 +386,136 @@ impl<'de> Deserializer<'de> for V<'de> {
         V: Visitor<'de>,
     {
         match self.0 {
-            Value::Text { value } => visitor.visit_some(value.to_string().into_deserializer()),
             Value::Null => visitor.visit_none(),
-            Value::Float { value } => visitor.visit_some(value.into_deserializer()),
-            Value::Integer { value } => visitor.visit_some(value.into_deserializer()),
-            Value::Blob { value } => {
-                let seq = SeqDeserializer::new(value.iter().cloned());
-                visitor.visit_some(seq)
-            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::Integer { value: 0 } => visitor.visit_bool(false),
+            Value::Integer { value: 1 } => visitor.visit_bool(true),
+            Value::Integer { value } => Err(DeError::custom(format!(
+                "{value} is not a valid bool, expected 0 or 1"
+            ))),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_int! {
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            // Enums with data are stored as JSON, e.g. `{"Variant":42}`
+            Value::Text { value } if value.starts_with('{') => self
+                .json()
+                .unwrap()
+                .deserialize_enum(name, variants, visitor)
+                .map_err(DeError::custom),
+            Value::Text { value } => visitor.visit_enum(value.as_str().into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.json() {
+            Some(mut json) => json.deserialize_seq(visitor).map_err(DeError::custom),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.json() {
+            Some(mut json) => json
+                .deserialize_tuple(len, visitor)
+                .map_err(DeError::custom),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.json() {
+            Some(mut json) => json
+                .deserialize_tuple_struct(name, len, visitor)
+                .map_err(DeError::custom),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.json() {
+            Some(mut json) => json.deserialize_map(visitor).map_err(DeError::custom),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.json() {
+            Some(mut json) => json
+                .deserialize_struct(name, fields, visitor)
+                .map_err(DeError::custom),
+            None => self.deserialize_any(visitor),
         }
     }
 
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf unit unit_struct newtype_struct seq tuple
-        tuple_struct map enum struct identifier ignored_any
+        i64 i128 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct identifier ignored_any
     }
 }
 
@@ -191,6 +539,7 @@ mod tests {
         bag: Option<Vec<u8>>,
     }
 
+    #[cfg(feature = "mapping_names_to_values_in_rows")]
     #[test]
     fn struct_from_row() {
         let mut row = Row {
@@ -238,4 +587,214 @@ mod tests {
         assert_eq!(foo.bac, None);
         assert_eq!(foo.bag, Some(vec![6u8; 128]));
     }
+
+    fn row(values: impl IntoIterator<Item = (&'static str, Value)>) -> Row {
+        let (columns, values): (Vec<String>, Vec<Value>) =
+            values.into_iter().map(|(k, v)| (k.to_string(), v)).unzip();
+        Row::from_values(&columns, values)
+    }
+
+    fn text(value: &str) -> Value {
+        Value::Text {
+            value: value.to_string(),
+        }
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    enum Role {
+        Admin,
+        Guest { until: i64 },
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct UserId(u32);
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Settings {
+        theme: String,
+        tags: Vec<String>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Rich {
+        id: UserId,
+        active: bool,
+        deleted: Option<bool>,
+        age: u8,
+        score: i32,
+        role: Role,
+        previous_role: Role,
+        settings: Settings,
+        scores: Vec<i64>,
+    }
+
+    #[cfg(feature = "mapping_names_to_values_in_rows")]
+    #[test]
+    fn rich_types_from_row() {
+        let row = row([
+            ("id", Value::Integer { value: 7 }),
+            ("active", Value::Integer { value: 1 }),
+            ("deleted", Value::Integer { value: 0 }),
+            ("age", Value::Integer { value: 42 }),
+            ("score", Value::Integer { value: -3 }),
+            ("role", text("Admin")),
+            ("previous_role", text(r#"{"Guest":{"until":5}}"#)),
+            ("settings", text(r#"{"theme":"dark","tags":["a","b"]}"#)),
+            ("scores", text("[1,2,3]")),
+        ]);
+
+        let rich = from_row::<Rich>(&row).unwrap();
+
+        assert_eq!(rich.id, UserId(7));
+        assert!(rich.active);
+        assert_eq!(rich.deleted, Some(false));
+        assert_eq!(rich.age, 42);
+        assert_eq!(rich.score, -3);
+        assert_eq!(rich.role, Role::Admin);
+        assert_eq!(rich.previous_role, Role::Guest { until: 5 });
+        assert_eq!(
+            rich.settings,
+            Settings {
+                theme: "dark".into(),
+                tags: vec!["a".into(), "b".into()]
+            }
+        );
+        assert_eq!(rich.scores, vec![1, 2, 3]);
+    }
+
+    #[cfg(feature = "mapping_names_to_values_in_rows")]
+    #[test]
+    fn invalid_values() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(unused)]
+        struct Age {
+            age: u8,
+        }
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(unused)]
+        struct Active {
+            active: bool,
+        }
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(unused)]
+        struct WithSettings {
+            settings: Settings,
+        }
+
+        let err = from_row::<Age>(&row([("age", Value::Integer { value: 300 })])).unwrap_err();
+        assert!(err.to_string().contains("out of range for u8"), "{err}");
+        assert!(from_row::<Age>(&row([("age", Value::Integer { value: -1 })])).is_err());
+        assert!(from_row::<Active>(&row([("active", Value::Integer { value: 2 })])).is_err());
+        assert!(from_row::<WithSettings>(&row([("settings", text("{"))])).is_err());
+    }
+
+    #[cfg(all(feature = "uuid", feature = "mapping_names_to_values_in_rows"))]
+    #[test]
+    fn uuid_from_row() {
+        #[derive(serde::Deserialize)]
+        struct Ids {
+            text: uuid::Uuid,
+            blob: uuid::Uuid,
+        }
+
+        let id = uuid::Uuid::from_u128(0x1234_5678_9abc_def0_1234_5678_9abc_def0);
+        let row = row([
+            ("text", text(&id.to_string())),
+            (
+                "blob",
+                Value::Blob {
+                    value: id.as_bytes().to_vec(),
+                },
+            ),
+        ]);
+
+        let ids = from_row::<Ids>(&row).unwrap();
+        assert_eq!(ids.text, id);
+        assert_eq!(ids.blob, id);
+    }
+
+    #[cfg(all(feature = "chrono", feature = "mapping_names_to_values_in_rows"))]
+    #[test]
+    fn datetime_from_row() {
+        #[derive(serde::Deserialize)]
+        struct Event {
+            #[serde(with = "crate::datetime")]
+            created_at: chrono::DateTime<chrono::Utc>,
+            #[serde(with = "crate::datetime::option")]
+            finished_at: Option<chrono::NaiveDateTime>,
+            #[serde(with = "crate::datetime::option")]
+            cancelled_at: Option<chrono::NaiveDateTime>,
+        }
+
+        let row = row([
+            ("created_at", text("2023-04-05 06:07:08")),
+            ("finished_at", Value::Integer { value: 1680674828 }),
+            ("cancelled_at", Value::Null),
+        ]);
+
+        let event = from_row::<Event>(&row).unwrap();
+        assert_eq!(event.created_at.timestamp(), 1680674828);
+        assert_eq!(event.finished_at, Some(event.created_at.naive_utc()));
+        assert_eq!(event.cancelled_at, None);
+    }
+
+    #[test]
+    fn tuple_from_values() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Pair(String, Option<i64>);
+
+        let values = row([("id", Value::Integer { value: 1 }), ("id", text("one"))]);
+
+        let tuple = from_values::<(u8, String)>(&values).unwrap();
+        assert_eq!(tuple, (1, "one".to_string()));
+
+        let values = row([("name", text("a")), ("age", Value::Null)]);
+        assert_eq!(
+            from_values::<Pair>(&values).unwrap(),
+            Pair("a".into(), None)
+        );
+
+        let values = row([
+            ("a", Value::Integer { value: 1 }),
+            ("b", Value::Integer { value: 2 }),
+        ]);
+        assert_eq!(from_values::<Vec<i64>>(&values).unwrap(), vec![1, 2]);
+        assert!(from_values::<(i64,)>(&values).is_err());
+        assert!(from_values::<(i64, i64, i64)>(&values).is_err());
+    }
+
+    #[test]
+    fn struct_from_columns() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Order {
+            order_id: i64,
+            user_id: i64,
+        }
+
+        let columns = vec!["order_id".to_string(), "user_id".to_string()];
+        let row = Row::from_values(
+            &columns,
+            vec![Value::Integer { value: 1 }, Value::Integer { value: 2 }],
+        );
+        assert_eq!(
+            from_columns::<Order>(&columns, &row).unwrap(),
+            Order {
+                order_id: 1,
+                user_id: 2
+            }
+        );
+        assert_eq!(from_columns::<(i64, i64)>(&columns, &row).unwrap(), (1, 2));
+        assert!(from_values::<Order>(&row).is_err());
+
+        let columns = vec!["id".to_string(), "id".to_string()];
+        let row = Row::from_values(
+            &columns,
+            vec![Value::Integer { value: 1 }, Value::Integer { value: 2 }],
+        );
+        let err = from_columns::<HashMap<String, i64>>(&columns, &row).unwrap_err();
+        assert!(
+            err.to_string().contains("Duplicate column name: id"),
+            "{err}"
+        );
+    }
 }
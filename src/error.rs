@@ -0,0 +1,346 @@
+//! `Error` is the error type returned by every fallible operation of this crate.
+
+/// Boxed error type used as the source of transport failures.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Result type used throughout this crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by [`Client`](crate::Client), its backends and the related helpers.
+///
+/// # Examples
+///
+/// ```
+/// # async fn f() {
+/// let db = libsql_client::Client::in_memory().unwrap();
+/// db.execute("create table t(id integer primary key)").await.unwrap();
+/// db.execute("insert into t values (1)").await.unwrap();
+/// match db.execute("insert into t values (1)").await {
+///     Err(e) if e.is_constraint_violation() => println!("duplicate: {e}"),
+///     Err(e) => panic!("unexpected error: {e}"),
+///     Ok(_) => unreachable!(),
+/// }
+/// # }
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request could not be sent, or the response could not be received.
+    #[error("transport error: {0}")]
+    Transport(#[source] BoxError),
+    /// The request did not complete within the configured timeout.
+    #[error("timeout: {0}")]
+    Timeout(String),
+    /// The server answered with a non-success HTTP status.
+    #[error("HTTP status {status}: {message}")]
+    Http { status: u16, message: String },
+    /// SQLite (or the server on its behalf) failed to execute a statement.
+    ///
+    /// `code` is the extended result code, if the backend reported one.
+    /// See <https://www.sqlite.org/rescode.html> for details.
+    ///
+    /// The WebSocket (hrana) backend never reports one: the underlying hrana client only
+    /// keeps the message of server errors. With that backend, `code` is always `None`, so
+    /// [`Error::is_busy`], [`Error::is_constraint_violation`] and [`Error::is_readonly`]
+    /// return false, and busy errors are not retried by
+    /// [`Client::with_transaction`](crate::Client::with_transaction).
+    #[error("SQLite error: {message}")]
+    Sqlite { code: Option<i32>, message: String },
+    /// The server sent a response that does not follow the protocol.
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    /// The stream or baton the request referred to is no longer valid on the server.
+    #[error("stream expired: {0}")]
+    StreamExpired(String),
+    /// The client was configured incorrectly, e.g. with an invalid URL.
+    #[error("configuration error: {0}")]
+    Config(String),
+    /// A value could not be converted to the requested type.
+    #[error("conversion error: {0}")]
+    Conversion(String),
+    /// Migrations could not be applied, e.g. because an applied migration was edited.
+    /// See [`migrate`](crate::migrate).
+    #[error("migration error: {0}")]
+    Migration(String),
+    /// A query expected to return a row returned none.
+    #[error("query returned no rows")]
+    NoRows,
+    /// A query expected to return at most one row returned more, their number is attached.
+    #[error("query returned {0} rows, expected at most one")]
+    TooManyRows(usize),
+    /// A statement of a batch failed.
+    ///
+    /// `step` is the index of the failed statement in the batch passed by the caller,
+    /// `sql` is its SQL text. The error of the statement is the [source](std::error::Error::source).
+    #[error("statement {step} of the batch failed (SQL: {sql})")]
+    Batch {
+        step: usize,
+        sql: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the extended SQLite result code, if this is a SQLite error with a known code.
+    pub fn sqlite_code(&self) -> Option<i32> {
+        match self {
+            Error::Sqlite { code, .. } => *code,
+            Error::Batch { source, .. } => source.sqlite_code(),
+            _ => None,
+        }
+    }
+
+    /// Returns the primary SQLite result code, i.e. the extended code with its
+    /// upper bits masked out.
+    pub fn primary_code(&self) -> Option<i32> {
+        self.sqlite_code().map(|code| code & 0xff)
+    }
+
+    /// True if a constraint (UNIQUE, NOT NULL, CHECK, FOREIGN KEY, ...) was violated.
+    pub fn is_constraint_violation(&self) -> bool {
+        self.primary_code() == Some(codes::SQLITE_CONSTRAINT)
+    }
+
+    /// True if the database was busy or locked, which usually means the operation
+    /// can be retried.
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self.primary_code(),
+            Some(codes::SQLITE_BUSY) | Some(codes::SQLITE_LOCKED)
+        )
+    }
+
+    /// True if a write was attempted on a read-only database.
+    pub fn is_readonly(&self) -> bool {
+        self.primary_code() == Some(codes::SQLITE_READONLY)
+    }
+
+    /// True if the stream or baton used by an interactive transaction expired.
+    pub fn is_stream_expired(&self) -> bool {
+        match self {
+            Error::StreamExpired(_) => true,
+            Error::Batch { source, .. } => source.is_stream_expired(),
+            _ => false,
+        }
+    }
+
+    /// True if the request was abandoned because it did not complete in time.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Error::Timeout(_) => true,
+            Error::Batch { source, .. } => source.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Returns the index of the failed statement, if this error comes from a batch.
+    pub fn batch_step(&self) -> Option<usize> {
+        match self {
+            Error::Batch { step, .. } => Some(*step),
+            _ => None,
+        }
+    }
+}
+
+/// SQLite result codes, see <https://www.sqlite.org/rescode.html>.
+pub mod codes {
+    pub const SQLITE_ERROR: i32 = 1;
+    pub const SQLITE_INTERNAL: i32 = 2;
+    pub const SQLITE_PERM: i32 = 3;
+    pub const SQLITE_ABORT: i32 = 4;
+    pub const SQLITE_BUSY: i32 = 5;
+    pub const SQLITE_LOCKED: i32 = 6;
+    pub const SQLITE_NOMEM: i32 = 7;
+    pub const SQLITE_READONLY: i32 = 8;
+    pub const SQLITE_INTERRUPT: i32 = 9;
+    pub const SQLITE_IOERR: i32 = 10;
+    pub const SQLITE_CORRUPT: i32 = 11;
+    pub const SQLITE_NOTFOUND: i32 = 12;
+    pub const SQLITE_FULL: i32 = 13;
+    pub const SQLITE_CANTOPEN: i32 = 14;
+    pub const SQLITE_PROTOCOL: i32 = 15;
+    pub const SQLITE_SCHEMA: i32 = 17;
+    pub const SQLITE_TOOBIG: i32 = 18;
+    pub const SQLITE_CONSTRAINT: i32 = 19;
+    pub const SQLITE_MISMATCH: i32 = 20;
+    pub const SQLITE_MISUSE: i32 = 21;
+    pub const SQLITE_AUTH: i32 = 23;
+    pub const SQLITE_RANGE: i32 = 25;
+    pub const SQLITE_NOTADB: i32 = 26;
+
+    pub const SQLITE_BUSY_RECOVERY: i32 = SQLITE_BUSY | (1 << 8);
+    pub const SQLITE_BUSY_SNAPSHOT: i32 = SQLITE_BUSY | (2 << 8);
+    pub const SQLITE_BUSY_TIMEOUT: i32 = SQLITE_BUSY | (3 << 8);
+    pub const SQLITE_LOCKED_SHAREDCACHE: i32 = SQLITE_LOCKED | (1 << 8);
+    pub const SQLITE_READONLY_RECOVERY: i32 = SQLITE_READONLY | (1 << 8);
+    pub const SQLITE_READONLY_CANTLOCK: i32 = SQLITE_READONLY | (2 << 8);
+    pub const SQLITE_READONLY_ROLLBACK: i32 = SQLITE_READONLY | (3 << 8);
+    pub const SQLITE_READONLY_DBMOVED: i32 = SQLITE_READONLY | (4 << 8);
+    pub const SQLITE_CONSTRAINT_CHECK: i32 = SQLITE_CONSTRAINT | (1 << 8);
+    pub const SQLITE_CONSTRAINT_FOREIGNKEY: i32 = SQLITE_CONSTRAINT | (3 << 8);
+    pub const SQLITE_CONSTRAINT_NOTNULL: i32 = SQLITE_CONSTRAINT | (5 << 8);
+    pub const SQLITE_CONSTRAINT_PRIMARYKEY: i32 = SQLITE_CONSTRAINT | (6 << 8);
+    pub const SQLITE_CONSTRAINT_TRIGGER: i32 = SQLITE_CONSTRAINT | (7 << 8);
+    pub const SQLITE_CONSTRAINT_UNIQUE: i32 = SQLITE_CONSTRAINT | (8 << 8);
+    pub const SQLITE_CONSTRAINT_ROWID: i32 = SQLITE_CONSTRAINT | (10 << 8);
+
+    const NAMES: &[(&str, i32)] = &[
+        ("SQLITE_ERROR", SQLITE_ERROR),
+        ("SQLITE_INTERNAL", SQLITE_INTERNAL),
+        ("SQLITE_PERM", SQLITE_PERM),
+        ("SQLITE_ABORT", SQLITE_ABORT),
+        ("SQLITE_BUSY", SQLITE_BUSY),
+        ("SQLITE_LOCKED", SQLITE_LOCKED),
+        ("SQLITE_NOMEM", SQLITE_NOMEM),
+        ("SQLITE_READONLY", SQLITE_READONLY),
+        ("SQLITE_INTERRUPT", SQLITE_INTERRUPT),
+        ("SQLITE_IOERR", SQLITE_IOERR),
+        ("SQLITE_CORRUPT", SQLITE_CORRUPT),
+        ("SQLITE_NOTFOUND", SQLITE_NOTFOUND),
+        ("SQLITE_FULL", SQLITE_FULL),
+        ("SQLITE_CANTOPEN", SQLITE_CANTOPEN),
+        ("SQLITE_PROTOCOL", SQLITE_PROTOCOL),
+        ("SQLITE_SCHEMA", SQLITE_SCHEMA),
+        ("SQLITE_TOOBIG", SQLITE_TOOBIG),
+        ("SQLITE_CONSTRAINT", SQLITE_CONSTRAINT),
+        ("SQLITE_MISMATCH", SQLITE_MISMATCH),
+        ("SQLITE_MISUSE", SQLITE_MISUSE),
+        ("SQLITE_AUTH", SQLITE_AUTH),
+        ("SQLITE_RANGE", SQLITE_RANGE),
+        ("SQLITE_NOTADB", SQLITE_NOTADB),
+        ("SQLITE_BUSY_RECOVERY", SQLITE_BUSY_RECOVERY),
+        ("SQLITE_BUSY_SNAPSHOT", SQLITE_BUSY_SNAPSHOT),
+        ("SQLITE_BUSY_TIMEOUT", SQLITE_BUSY_TIMEOUT),
+        ("SQLITE_LOCKED_SHAREDCACHE", SQLITE_LOCKED_SHAREDCACHE),
+        ("SQLITE_READONLY_RECOVERY", SQLITE_READONLY_RECOVERY),
+        ("SQLITE_READONLY_CANTLOCK", SQLITE_READONLY_CANTLOCK),
+        ("SQLITE_READONLY_ROLLBACK", SQLITE_READONLY_ROLLBACK),
+        ("SQLITE_READONLY_DBMOVED", SQLITE_READONLY_DBMOVED),
+        ("SQLITE_CONSTRAINT_CHECK", SQLITE_CONSTRAINT_CHECK),
+        ("SQLITE_CONSTRAINT_FOREIGNKEY", SQLITE_CONSTRAINT_FOREIGNKEY),
+        ("SQLITE_CONSTRAINT_NOTNULL", SQLITE_CONSTRAINT_NOTNULL),
+        ("SQLITE_CONSTRAINT_PRIMARYKEY", SQLITE_CONSTRAINT_PRIMARYKEY),
+        ("SQLITE_CONSTRAINT_TRIGGER", SQLITE_CONSTRAINT_TRIGGER),
+        ("SQLITE_CONSTRAINT_UNIQUE", SQLITE_CONSTRAINT_UNIQUE),
+        ("SQLITE_CONSTRAINT_ROWID", SQLITE_CONSTRAINT_ROWID),
+    ];
+
+    /// Maps a code name, as sent by sqld (e.g. `SQLITE_CONSTRAINT_UNIQUE`), to its numeric value.
+    pub fn from_name(name: &str) -> Option<i32> {
+        NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, code)| *code)
+    }
+
+    /// Maps a numeric code to its name, falling back to the name of the primary code
+    /// for extended codes that are not listed here.
+    pub fn name(code: i32) -> Option<&'static str> {
+        let lookup = |code| NAMES.iter().find(|(_, c)| *c == code).map(|(n, _)| *n);
+        lookup(code).or_else(|| lookup(code & 0xff))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Config(format!("Failed to parse url: {e}"))
+    }
+}
+
+impl From<serde::de::value::Error> for Error {
+    fn from(e: serde::de::value::Error) -> Self {
+        Error::Conversion(e.to_string())
+    }
+}
+
+#[cfg(feature = "reqwest_backend")]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Error::Timeout(e.to_string())
+        } else {
+            Error::Transport(Box::new(e))
+        }
+    }
+}
+
+#[cfg(feature = "hrana_backend")]
+impl From<hrana_client::error::Error> for Error {
+    fn from(e: hrana_client::error::Error) -> Self {
+        use hrana_client::error::Error as HranaError;
+        match e {
+            HranaError::HranaError(e) => crate::proto::Error::from(e).into(),
+            HranaError::StreamClosed | HranaError::StreamDoesNotExist => {
+                Error::StreamExpired(e.to_string())
+            }
+            HranaError::MissingHost | HranaError::InvalidUrl(_) => Error::Config(e.to_string()),
+            HranaError::BadResponse
+            | HranaError::InvalidServerMessage
+            | HranaError::RequestDoesNotExist
+            | HranaError::InvalidState => Error::Protocol(e.to_string()),
+            _ => Error::Transport(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(feature = "local_backend")]
+impl From<libsql::Error> for Error {
+    fn from(e: libsql::Error) -> Self {
+        match e {
+            libsql::Error::PrepareFailed(code, _, message)
+            | libsql::Error::FetchRowFailed(code, message)
+            | libsql::Error::LibError(code, message) => Error::Sqlite {
+                code: Some(code),
+                message,
+            },
+            libsql::Error::ConnectionFailed(_) => Error::Transport(Box::new(e)),
+            other => Error::Sqlite {
+                code: None,
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl From<crate::proto::Error> for Error {
+    fn from(e: crate::proto::Error) -> Self {
+        match e.code.as_deref() {
+            Some(code) if code.starts_with("BATON_") || code == "STREAM_EXPIRED" => {
+                Error::StreamExpired(e.message)
+            }
+            code => Error::Sqlite {
+                code: code.and_then(codes::from_name),
+                message: e.message,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn batch_error_source() {
+        let e = Error::Batch {
+            step: 1,
+            sql: "INSERT INTO t VALUES (1)".into(),
+            source: Box::new(Error::Sqlite {
+                code: Some(codes::SQLITE_CONSTRAINT_UNIQUE),
+                message: "UNIQUE constraint failed: t.id".into(),
+            }),
+        };
+        assert!(e.is_constraint_violation());
+        assert!(!e.to_string().contains("UNIQUE constraint failed"));
+        let source = e.source().unwrap().to_string();
+        assert_eq!(source, "SQLite error: UNIQUE constraint failed: t.id");
+    }
+}
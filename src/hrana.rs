@@ -1,10 +1,14 @@
-use crate::client::Config;
-use anyhow::Result;
+use crate::client::{Config, PoolConfig};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::RwLock;
 
-use crate::{utils, BatchResult, ResultSet, Statement};
+use crate::batch::Emulation;
+use crate::hrana_pool::{Pool, PooledStream};
+use crate::{
+    proto::StmtResult, utils, BatchBuilder, BatchCond, BatchResult, Error, PreparedStatement,
+    Result, ResultSet, RowStream, Statement,
+};
 
 /// Database client. This is the main structure used to
 /// communicate with the database.
@@ -12,9 +16,10 @@ pub struct Client {
     url: String,
     token: Option<String>,
 
-    client: hrana_client::Client,
-    client_future: hrana_client::ConnFut,
-    streams_for_transactions: RwLock<HashMap<u64, Arc<hrana_client::Stream>>>,
+    pool: Pool,
+    /// Connections to the read replica serving read-only transactions
+    replica_pool: Option<Pool>,
+    streams_for_transactions: RwLock<HashMap<u64, Arc<PooledStream>>>,
 }
 
 impl std::fmt::Debug for Client {
@@ -33,27 +38,44 @@ impl Client {
     /// * `url` - URL of the database endpoint
     /// * `token` - auth token
     pub async fn new(url: impl Into<String>, token: impl Into<String>) -> Result<Self> {
+        Self::with_pool(url, token, PoolConfig::default()).await
+    }
+
+    /// Creates a database client with JWT authentication, which spreads its streams
+    /// across a pool of WebSocket connections.
+    ///
+    /// # Arguments
+    /// * `url` - URL of the database endpoint
+    /// * `token` - auth token
+    /// * `pool_config` - size and eviction settings of the connection pool
+    pub async fn with_pool(
+        url: impl Into<String>,
+        token: impl Into<String>,
+        pool_config: PoolConfig,
+    ) -> Result<Self> {
         let token = token.into();
         let token = if token.is_empty() { None } else { Some(token) };
         let url = url.into();
 
-        let (client, client_future) = hrana_client::Client::connect(&url, token.clone()).await?;
+        let pool = Pool::new(url.clone(), token.clone(), pool_config).await?;
 
         Ok(Self {
             url,
             token,
-            client,
-            client_future,
+            pool,
+            replica_pool: None,
             streams_for_transactions: RwLock::new(HashMap::new()),
         })
     }
 
-    pub async fn reconnect(&mut self) -> Result<()> {
-        let (client, client_future) =
-            hrana_client::Client::connect(&self.url, self.token.clone()).await?;
-        self.client = client;
-        self.client_future = client_future;
-        Ok(())
+    /// Closes all WebSocket connections and opens new ones.
+    ///
+    /// Calling it is rarely needed: connections found dead are replaced automatically,
+    /// with exponential backoff between failed attempts (see [PoolConfig]).
+    /// Interactive transactions started before reconnecting fail with
+    /// [`Error::StreamExpired`] on their next statement.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.pool.reconnect().await
     }
 
     /// Creates a database client, given a `Url`
@@ -73,13 +95,13 @@ impl Client {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn from_url<T: TryInto<url::Url>>(url: T) -> anyhow::Result<Client>
+    pub async fn from_url<T: TryInto<url::Url>>(url: T) -> Result<Client>
     where
         <T as TryInto<url::Url>>::Error: std::fmt::Display,
     {
         let mut url: url::Url = url
             .try_into()
-            .map_err(|e| anyhow::anyhow!(format!("{e}")))?;
+            .map_err(|e| Error::Config(format!("Failed to parse url: {e}")))?;
         // remove the auth token from the URL so that it doesn't get logged anywhere
         let token = utils::pop_query_param(&mut url, "authToken".to_string());
         let url_str = if url.scheme() == "libsql" {
@@ -97,17 +119,47 @@ impl Client {
 
     /// Creates a database client from a `Config` object.
     pub async fn from_config(config: Config) -> Result<Self> {
-        Self::new(config.url, config.auth_token.unwrap_or_default()).await
+        let mut client = Self::with_pool(
+            config.url,
+            config.auth_token.unwrap_or_default(),
+            config.pool.clone(),
+        )
+        .await?;
+        if let Some(url) = config.read_replica {
+            client.replica_pool =
+                Some(Pool::new(url.to_string(), client.token.clone(), config.pool).await?);
+        }
+        Ok(client)
     }
 
-    pub async fn shutdown(self) -> Result<()> {
-        self.client.shutdown().await?;
-        self.client_future.await?;
+    /// Opens the stream of the transaction `tx_id` on the read replica, if there is one.
+    pub async fn use_read_replica(&self, tx_id: u64) -> Result<()> {
+        if let Some(pool) = &self.replica_pool {
+            let stream = Arc::new(pool.open_stream().await?);
+            let mut streams = self.streams_for_transactions.write().unwrap();
+            streams.insert(tx_id, stream);
+        }
         Ok(())
     }
 
+    // Whether the connection a stream was opened on, to the database or its replica, is alive.
+    fn is_connected(&self, stream: &PooledStream) -> bool {
+        self.pool.is_connected(stream)
+            || self
+                .replica_pool
+                .as_ref()
+                .is_some_and(|pool| pool.is_connected(stream))
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
+        if let Some(pool) = &self.replica_pool {
+            pool.shutdown().await?;
+        }
+        self.pool.shutdown().await
+    }
+
     // Find an existing stream for given transaction id, or create a new one.
-    async fn stream_for_transaction(&self, tx_id: u64) -> Result<Arc<hrana_client::Stream>> {
+    async fn stream_for_transaction(&self, tx_id: u64) -> Result<Arc<PooledStream>> {
         // Fast path, transaction exists and has a stream.
         {
             let streams = self.streams_for_transactions.read().unwrap();
@@ -119,7 +171,7 @@ impl Client {
         // Pessimistic path - let's drop the mutex, create the stream and try to reinsert it.
         // Another way out of this situation is an async mutex, but I don't want to rely on Tokio or any other specific runtime
         // unless absolutely necessary.
-        let stream = Arc::new(self.client.open_stream().await?);
+        let stream = Arc::new(self.pool.open_stream().await?);
         tracing::trace!("Created new stream");
         let mut streams = self.streams_for_transactions.write().unwrap();
         if let std::collections::hash_map::Entry::Vacant(e) = streams.entry(tx_id) {
@@ -140,6 +192,9 @@ impl Client {
         for param in stmt.args {
             hrana_stmt.bind(param);
         }
+        for (name, value) in stmt.named_args {
+            hrana_stmt.bind_named(name, value);
+        }
         hrana_stmt
     }
 }
@@ -148,65 +203,307 @@ impl Client {
     pub async fn raw_batch(
         &self,
         stmts: impl IntoIterator<Item = impl Into<Statement>>,
-    ) -> anyhow::Result<BatchResult> {
+    ) -> Result<BatchResult> {
         let mut batch = hrana_client::proto::Batch::new();
         for stmt in stmts.into_iter() {
-            let stmt: Statement = stmt.into();
-            let mut hrana_stmt = hrana_client::proto::Stmt::new(stmt.sql, true);
-            for param in stmt.args {
-                hrana_stmt.bind(param);
-            }
-            batch.step(None, hrana_stmt);
+            batch.step(None, Self::into_hrana(stmt.into()));
         }
 
-        let stream = self.client.open_stream().await?;
+        let stream = self.pool.open_stream().await?;
         stream
             .execute_batch(batch)
             .await
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .map(BatchResult::from)
+            .map_err(Error::from)
+    }
+
+    /// Executes a batch whose steps may be conditional, see [BatchBuilder].
+    ///
+    /// The conditions of the underlying hrana client are not serialized the way the server
+    /// expects, so conditional batches are evaluated here instead, by executing their steps
+    /// one at a time on a single stream.
+    pub async fn execute_batch(&self, batch: BatchBuilder) -> Result<BatchResult> {
+        if batch.is_unconditional() {
+            return self
+                .raw_batch(batch.into_steps().into_iter().map(|(_, stmt)| stmt))
+                .await;
+        }
+        let stream = self.pool.open_stream().await?;
+        let mut emulation = Emulation::default();
+        for (cond, stmt) in batch.into_steps() {
+            if !emulation.should_run(cond.as_ref()) {
+                emulation.record(None);
+                continue;
+            }
+            match stream
+                .execute(Self::into_hrana(stmt))
+                .await
+                .map_err(Error::from)
+            {
+                Ok(result) => emulation.record(Some(Ok(StmtResult::from(result)))),
+                Err(e @ Error::Sqlite { .. }) => emulation.record(Some(Err(e))),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(emulation.finish())
     }
 
     pub async fn execute(&self, stmt: impl Into<Statement>) -> Result<ResultSet> {
         let stmt = Self::into_hrana(stmt.into());
 
-        let stream = self.client.open_stream().await?;
+        let stream = self.pool.open_stream().await?;
         stream
             .execute(stmt)
             .await
-            .map(ResultSet::from)
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .map(|result| ResultSet::from(StmtResult::from(result)))
+            .map_err(Error::from)
+    }
+
+    /// Creates a [PreparedStatement].
+    ///
+    /// The underlying hrana client only speaks version 1 of the Hrana protocol, which has
+    /// no requests to store SQL on the server, so statements created from it are still
+    /// sent with their full SQL text.
+    pub fn prepare(&self, sql: impl Into<String>) -> Result<PreparedStatement> {
+        Ok(PreparedStatement::new(sql.into()))
+    }
+
+    /// Executes a statement and returns its rows as a stream.
+    ///
+    /// The WebSocket protocol spoken by this backend has no cursors, so all the rows
+    /// are received at once and then yielded one by one.
+    pub async fn query_stream(&self, stmt: impl Into<Statement>) -> Result<RowStream> {
+        let rows = self.execute(stmt).await?.rows;
+        Ok(Box::pin(futures::stream::iter(rows.into_iter().map(Ok))))
     }
 
     pub async fn execute_in_transaction(&self, tx_id: u64, stmt: Statement) -> Result<ResultSet> {
         let stmt = Self::into_hrana(stmt);
         tracing::trace!("Transaction {tx_id} executing {}", stmt.sql);
         let stream = self.stream_for_transaction(tx_id).await?;
-        stream
-            .execute(stmt)
+        self.execute_in_stream(tx_id, &stream, stmt)
             .await
             .map(ResultSet::from)
-            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    /// Executes statements on the stream of the transaction.
+    ///
+    /// Each statement only runs if the previous one succeeded, so the statements
+    /// following a failed one are not executed. As the underlying hrana client cannot send
+    /// that condition (see [Client::execute_batch]), the statements are executed one at a time.
+    pub async fn batch_in_transaction(
+        &self,
+        tx_id: u64,
+        stmts: Vec<Statement>,
+    ) -> Result<BatchResult> {
+        tracing::trace!("Transaction {tx_id} executing a batch");
+        let stream = self.stream_for_transaction(tx_id).await?;
+        let mut emulation = Emulation::default();
+        for (i, stmt) in stmts.into_iter().enumerate() {
+            let cond = i
+                .checked_sub(1)
+                .map(|step| BatchCond::Ok { step: step as u32 });
+            if !emulation.should_run(cond.as_ref()) {
+                emulation.record(None);
+                continue;
+            }
+            match self
+                .execute_in_stream(tx_id, &stream, Self::into_hrana(stmt))
+                .await
+            {
+                Ok(result) => emulation.record(Some(Ok(result))),
+                Err(e @ Error::Sqlite { .. }) => emulation.record(Some(Err(e))),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(emulation.finish())
     }
 
     pub async fn commit_transaction(&self, tx_id: u64) -> Result<()> {
         tracing::trace!("Transaction {tx_id} commit");
         let stream = self.stream_for_transaction(tx_id).await?;
         self.drop_stream_for_transaction(tx_id);
-        stream
+        if !self.is_connected(&stream) {
+            return Err(Error::StreamExpired(
+                "transaction lost on reconnect".to_string(),
+            ));
+        }
+        // Once COMMIT was sent, only an answer of the server tells whether it was applied,
+        // so a connection lost in the meantime is not reported as a lost transaction.
+        match stream
             .execute(Self::into_hrana(Statement::from("COMMIT")))
             .await
-            .map(|_| ())
-            .map_err(|e| anyhow::anyhow!("{}", e))
+        {
+            Ok(_) => Ok(()),
+            Err(e @ hrana_client::error::Error::HranaError(_)) => Err(e.into()),
+            Err(e) => Err(Error::Transport(Box::new(e))),
+        }
     }
 
     pub async fn rollback_transaction(&self, tx_id: u64) -> Result<()> {
         tracing::trace!("Transaction {tx_id} rollback");
         let stream = self.stream_for_transaction(tx_id).await?;
         self.drop_stream_for_transaction(tx_id);
-        stream
-            .execute(Self::into_hrana(Statement::from("ROLLBACK")))
+        self.execute_in_stream(
+            tx_id,
+            &stream,
+            Self::into_hrana(Statement::from("ROLLBACK")),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Releases the stream of a transaction dropped without being committed or rolled back.
+    /// Closing the stream makes the server roll the transaction back.
+    pub fn drop_transaction(&self, tx_id: u64) {
+        self.drop_stream_for_transaction(tx_id);
+    }
+
+    async fn execute_in_stream(
+        &self,
+        tx_id: u64,
+        stream: &PooledStream,
+        stmt: hrana_client::proto::Stmt,
+    ) -> Result<StmtResult> {
+        self.in_stream(tx_id, stream, stream.execute(stmt))
+            .await
+            .map(StmtResult::from)
+    }
+
+    // Sends a request on the stream of an interactive transaction.
+    // A transaction cannot outlive the connection its stream was opened on,
+    // so if that connection is gone, the transaction is reported as lost.
+    async fn in_stream<T>(
+        &self,
+        tx_id: u64,
+        stream: &PooledStream,
+        request: impl std::future::Future<Output = hrana_client::error::Result<T>>,
+    ) -> Result<T> {
+        if !self.is_connected(stream) {
+            self.drop_stream_for_transaction(tx_id);
+            return Err(Error::StreamExpired(
+                "transaction lost on reconnect".to_string(),
+            ));
+        }
+        match request.await {
+            Ok(result) => Ok(result),
+            Err(e) if !self.is_connected(stream) => {
+                self.drop_stream_for_transaction(tx_id);
+                Err(Error::StreamExpired(format!(
+                    "transaction lost on reconnect: {e}"
+                )))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod tests {
+    use crate::hrana_test_server::TestServer;
+    use crate::{BatchBuilder, ResultSet, RetryPolicy};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn client(server: &TestServer) -> crate::Client {
+        crate::Client::Hrana(super::Client::new(server.url(), "").await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn read_only_transaction_served_by_replica() {
+        let primary = TestServer::start().await;
+        let replica = TestServer::start().await;
+        client(&replica)
+            .await
+            .batch(["CREATE TABLE t(x)", "INSERT INTO t VALUES (1)"])
+            .await
+            .unwrap();
+        let config = crate::Config::new(primary.url())
+            .unwrap()
+            .with_read_replica(replica.url())
+            .unwrap();
+        let db = crate::Client::Hrana(super::Client::from_config(config).await.unwrap());
+
+        let tx = db
+            .transaction_with(crate::TransactionBehavior::ReadOnly)
+            .await
+            .unwrap();
+        let rs = tx.execute("SELECT x FROM t").await.unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(1,)]);
+        tx.commit().await.unwrap();
+
+        // The table only exists on the replica
+        let tx = db.transaction().await.unwrap();
+        assert!(tx.execute("SELECT x FROM t").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn commit_with_unknown_outcome_not_retried() {
+        let server = TestServer::start().await;
+        let db = client(&server).await;
+        db.execute("CREATE TABLE t(x)").await.unwrap();
+
+        server.drop_on_commit(true);
+        let attempts = AtomicUsize::new(0);
+        let err = db
+            .with_transaction_retry(&RetryPolicy::default(), |tx| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move { tx.execute("INSERT INTO t VALUES (1)").await })
+            })
+            .await
+            .unwrap_err();
+        assert!(!err.is_stream_expired(), "{err}");
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        server.drop_on_commit(false);
+        let count: i64 = db
+            .execute("SELECT count(*) FROM t")
+            .await
+            .unwrap()
+            .scalar()
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn conditional_batch_emulated() {
+        let server = TestServer::start().await;
+        let db = client(&server).await;
+
+        let mut batch = BatchBuilder::new();
+        let create = batch.step("CREATE TABLE t(x)");
+        batch.step_if(create.ok(), "INSERT INTO t VALUES (1)");
+        let duplicate = batch.step("CREATE TABLE t(x)");
+        batch.step_if(duplicate.ok(), "INSERT INTO t VALUES (2)");
+        batch.step_if(duplicate.error(), "INSERT INTO t VALUES (3)");
+        batch.step("SELECT x FROM t");
+        let result = db.execute_batch(batch).await.unwrap();
+
+        let ran: Vec<bool> = result.step_results.iter().map(Option::is_some).collect();
+        assert_eq!(ran, [true, true, false, false, true, true]);
+        assert!(result.step_errors[2].is_some());
+        assert!(result.step_errors[3].is_none());
+        let rows = ResultSet::from(result.step_results[5].clone().unwrap()).rows;
+        assert_eq!(rows.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn transaction_batch_stops_at_failed_step() {
+        let server = TestServer::start().await;
+        let db = client(&server).await;
+        db.execute("CREATE TABLE t(x)").await.unwrap();
+
+        let tx = db.transaction().await.unwrap();
+        let err = tx
+            .batch([
+                "INSERT INTO t VALUES (1)",
+                "INSERT INTO missing VALUES (2)",
+                "INSERT INTO t VALUES (3)",
+            ])
             .await
-            .map(|_| ())
-            .map_err(|e| anyhow::anyhow!("{}", e))
+            .unwrap_err();
+        assert_eq!(err.batch_step(), Some(1));
+        let rows = tx.execute("SELECT x FROM t").await.unwrap().rows;
+        assert_eq!(rows.len(), 1);
+        tx.rollback().await.unwrap();
     }
 }
@@ -0,0 +1,575 @@
+//! Pool of WebSocket connections used by the [hrana](crate::hrana) backend.
+
+use futures::FutureExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Error, PoolConfig, Result};
+
+/// Upper bound of the delay between two reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A single WebSocket connection, along with the number of streams currently opened on it.
+struct Connection {
+    client: hrana_client::Client,
+    client_future: hrana_client::ConnFut,
+    active_streams: Arc<AtomicUsize>,
+    last_used: Instant,
+    last_checked: Instant,
+}
+
+impl Connection {
+    async fn connect(url: &str, token: Option<String>) -> Result<Self> {
+        let (client, client_future) = hrana_client::Client::connect(url, token).await?;
+        let now = Instant::now();
+        Ok(Self {
+            client,
+            client_future,
+            active_streams: Arc::new(AtomicUsize::new(0)),
+            last_used: now,
+            last_checked: now,
+        })
+    }
+
+    // The future driving the connection only completes once the socket is closed.
+    // It must not be polled again after it reports completion, so the connection
+    // has to be evicted right after that.
+    fn is_closed(&mut self) -> bool {
+        (&mut self.client_future).now_or_never().is_some()
+    }
+
+    fn load(&self) -> usize {
+        self.active_streams.load(Ordering::Relaxed)
+    }
+}
+
+/// Reservation of a stream on a connection, released when dropped.
+struct StreamSlot(Arc<AtomicUsize>);
+
+impl StreamSlot {
+    fn reserve(active_streams: &Arc<AtomicUsize>) -> Self {
+        active_streams.fetch_add(1, Ordering::Relaxed);
+        Self(active_streams.clone())
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Connection picked for a new stream: its client, the reserved slot and
+/// whether it is due for a health check.
+type Picked = (hrana_client::Client, StreamSlot, bool);
+
+/// Outcome of looking for a connection to open a new stream on.
+enum Pick<'a> {
+    /// An existing connection was picked
+    Connection(Picked),
+    /// A new connection should be opened, its place in the pool is reserved
+    Connect(ConnectSlot<'a>),
+    /// All the connections the pool may hold are still being opened
+    Wait,
+}
+
+/// Place in the pool reserved for a connection being opened, released when dropped.
+///
+/// Connections being opened are counted along with the open ones, so that concurrent
+/// callers cannot open more than `max_connections` between them.
+struct ConnectSlot<'a>(&'a Pool);
+
+impl Drop for ConnectSlot<'_> {
+    fn drop(&mut self) {
+        // Released under the lock taken when reserving, so that pick_connection
+        // never sees the count change in the middle of its decision
+        let _connections = self.0.connections.lock().unwrap();
+        self.0.connecting.fetch_sub(1, Ordering::Relaxed);
+        self.0.connected.notify_waiters();
+    }
+}
+
+/// A stream opened on one of the pooled connections.
+///
+/// The connection counts it as active until it is dropped.
+pub(crate) struct PooledStream {
+    stream: hrana_client::Stream,
+    slot: StreamSlot,
+}
+
+impl std::ops::Deref for PooledStream {
+    type Target = hrana_client::Stream;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+/// Pool of hrana connections. Streams are spread across connections, preferring
+/// the least loaded one and opening new connections, up to `max_connections`,
+/// when all existing ones are busy.
+pub(crate) struct Pool {
+    url: String,
+    token: Option<String>,
+    config: PoolConfig,
+    connections: Mutex<Vec<Connection>>,
+    /// Number of connections being opened, only changed with `connections` locked
+    connecting: AtomicUsize,
+    /// Notified whenever a connection being opened was added to the pool or failed
+    connected: tokio::sync::Notify,
+}
+
+impl Pool {
+    /// Creates a pool and eagerly opens `min_connections` connections (at least one,
+    /// so that invalid URLs or credentials are reported right away).
+    pub(crate) async fn new(
+        url: String,
+        token: Option<String>,
+        config: PoolConfig,
+    ) -> Result<Self> {
+        if config.max_connections == 0 || config.min_connections > config.max_connections {
+            return Err(Error::Config(format!(
+                "Invalid pool size: min_connections={}, max_connections={}",
+                config.min_connections, config.max_connections
+            )));
+        }
+        let mut connections = Vec::new();
+        for _ in 0..config.min_connections.max(1) {
+            connections.push(Connection::connect(&url, token.clone()).await?);
+        }
+        Ok(Self {
+            url,
+            token,
+            config,
+            connections: Mutex::new(connections),
+            connecting: AtomicUsize::new(0),
+            connected: tokio::sync::Notify::new(),
+        })
+    }
+
+    /// Opens a new stream on the least loaded healthy connection.
+    ///
+    /// Connections found dead along the way are evicted and replaced, so a dropped
+    /// WebSocket is transparently reconnected. A health check which fails on a connection
+    /// that is still open only fails the new stream, the connection is kept.
+    pub(crate) async fn open_stream(&self) -> Result<PooledStream> {
+        // Each failed attempt evicts a connection, so this loop is bounded
+        // by the number of connections in the pool.
+        loop {
+            // Created before picking, so that a connection added in the meantime wakes it up
+            let connected = self.connected.notified();
+            let (pick, idle) = self.pick_connection();
+            for conn in idle {
+                tracing::debug!("Closing idle hrana connection");
+                conn.client.shutdown().await.ok();
+            }
+            let (client, slot, needs_check) = match pick {
+                Pick::Connection(picked) => picked,
+                Pick::Connect(reserved) => self.add_connection(reserved).await?,
+                Pick::Wait => {
+                    connected.await;
+                    continue;
+                }
+            };
+            let stream = match client.open_stream().await {
+                Ok(stream) => stream,
+                // Nothing was sent yet, so it's safe to try again on another connection
+                Err(hrana_client::error::Error::Shutdown) => {
+                    tracing::debug!("Evicting closed hrana connection");
+                    self.evict(&slot);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let stream = PooledStream { stream, slot };
+            if needs_check {
+                match stream.wait_for_open().await {
+                    Ok(()) => {}
+                    Err(hrana_client::error::Error::Shutdown) => {
+                        tracing::warn!("Evicting closed hrana connection");
+                        self.evict(&stream.slot);
+                        continue;
+                    }
+                    // The server answered, so the connection itself is fine and the streams
+                    // of other transactions opened on it must not be lost.
+                    Err(e) if self.is_connected(&stream) => return Err(e.into()),
+                    Err(e) => {
+                        tracing::warn!("Hrana connection closed during health check: {e}");
+                        continue;
+                    }
+                }
+            }
+            return Ok(stream);
+        }
+    }
+
+    /// Replaces all connections with new ones. Streams opened on the old connections
+    /// are no longer [connected](Pool::is_connected).
+    pub(crate) async fn reconnect(&self) -> Result<()> {
+        let old = std::mem::take(&mut *self.connections.lock().unwrap());
+        for conn in old {
+            conn.client.shutdown().await.ok();
+        }
+        for _ in 0..self.config.min_connections.max(1) {
+            let conn = self.connect_with_backoff().await?;
+            self.connections.lock().unwrap().push(conn);
+        }
+        Ok(())
+    }
+
+    /// True if the connection the stream was opened on is still alive and part of the pool.
+    pub(crate) fn is_connected(&self, stream: &PooledStream) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        connections.retain_mut(|conn| !conn.is_closed());
+        connections
+            .iter()
+            .any(|conn| Arc::ptr_eq(&conn.active_streams, &stream.slot.0))
+    }
+
+    /// Closes all the connections and drops them from the pool.
+    pub(crate) async fn shutdown(&self) -> Result<()> {
+        let connections = std::mem::take(&mut *self.connections.lock().unwrap());
+        for conn in connections {
+            conn.client.shutdown().await?;
+            conn.client_future.await?;
+        }
+        Ok(())
+    }
+
+    // Picks a connection for a new stream, or reserves the place of a new one.
+    // Also removes closed connections, and returns the idle ones that should be shut down.
+    fn pick_connection(&self) -> (Pick<'_>, Vec<Connection>) {
+        let mut connections = self.connections.lock().unwrap();
+        let now = Instant::now();
+        let before = connections.len();
+        connections.retain_mut(|conn| !conn.is_closed());
+        if connections.len() < before {
+            tracing::debug!(
+                "Dropped {} closed hrana connection(s)",
+                before - connections.len()
+            );
+        }
+        let mut idle = Vec::new();
+        if let Some(idle_timeout) = self.config.idle_timeout {
+            while connections.len() > self.config.min_connections.max(1) {
+                match connections.iter().position(|conn| {
+                    conn.load() == 0 && now.duration_since(conn.last_used) > idle_timeout
+                }) {
+                    Some(i) => idle.push(connections.swap_remove(i)),
+                    None => break,
+                }
+            }
+        }
+        let connecting = self.connecting.load(Ordering::Relaxed);
+        let can_grow = connections.len() + connecting < self.config.max_connections;
+        let picked = connections
+            .iter_mut()
+            .min_by_key(|conn| conn.load())
+            .filter(|conn| conn.load() == 0 || !can_grow)
+            .map(|conn| {
+                conn.last_used = now;
+                let needs_check = match self.config.health_check_interval {
+                    Some(interval) if now.duration_since(conn.last_checked) > interval => {
+                        conn.last_checked = now;
+                        true
+                    }
+                    _ => false,
+                };
+                (
+                    conn.client.clone(),
+                    StreamSlot::reserve(&conn.active_streams),
+                    needs_check,
+                )
+            });
+        let pick = match picked {
+            Some(picked) => Pick::Connection(picked),
+            None if can_grow => {
+                self.connecting.fetch_add(1, Ordering::Relaxed);
+                Pick::Connect(ConnectSlot(self))
+            }
+            None => Pick::Wait,
+        };
+        (pick, idle)
+    }
+
+    async fn add_connection(&self, reserved: ConnectSlot<'_>) -> Result<Picked> {
+        tracing::debug!("Opening a new hrana connection");
+        let conn = self.connect_with_backoff().await?;
+        let picked = (
+            conn.client.clone(),
+            StreamSlot::reserve(&conn.active_streams),
+            false,
+        );
+        self.connections.lock().unwrap().push(conn);
+        drop(reserved);
+        Ok(picked)
+    }
+
+    // Connects to the server, retrying with exponential backoff.
+    async fn connect_with_backoff(&self) -> Result<Connection> {
+        let mut delay = self.config.reconnect_backoff;
+        let mut attempt = 1;
+        loop {
+            match Connection::connect(&self.url, self.token.clone()).await {
+                Ok(conn) => return Ok(conn),
+                Err(e) if attempt < self.config.max_reconnect_attempts => {
+                    tracing::warn!(
+                        "Connecting to {} failed (attempt {attempt}), retrying in {delay:?}: {e}",
+                        self.url
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_BACKOFF);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn evict(&self, slot: &StreamSlot) {
+        self.connections
+            .lock()
+            .unwrap()
+            .retain(|conn| !Arc::ptr_eq(&conn.active_streams, &slot.0));
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod tests {
+    use super::*;
+    use crate::hrana_test_server::TestServer;
+
+    async fn pool(server: &TestServer, config: PoolConfig) -> Pool {
+        Pool::new(server.url().to_string(), None, config)
+            .await
+            .unwrap()
+    }
+
+    fn on_same_connection(a: &PooledStream, b: &PooledStream) -> bool {
+        Arc::ptr_eq(&a.slot.0, &b.slot.0)
+    }
+
+    #[tokio::test]
+    async fn picks_least_loaded_connection() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                max_connections: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let first = pool.open_stream().await.unwrap();
+        let second = pool.open_stream().await.unwrap();
+        assert!(!on_same_connection(&first, &second));
+        assert_eq!(server.accepted(), 2);
+
+        // A stream goes to a connection with no streams before a new one is opened
+        let first_connection = first.slot.0.clone();
+        drop(first);
+        let third = pool.open_stream().await.unwrap();
+        assert!(Arc::ptr_eq(&third.slot.0, &first_connection));
+        assert_eq!(server.accepted(), 2);
+
+        // When all connections are busy and no new one can be opened,
+        // the one with the fewest streams is picked
+        let fourth = pool.open_stream().await.unwrap();
+        let fifth = pool.open_stream().await.unwrap();
+        assert!(!on_same_connection(&fourth, &fifth));
+        assert_eq!(server.accepted(), 2);
+        drop((second, third));
+    }
+
+    #[tokio::test]
+    async fn concurrent_streams_respect_max_connections() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                max_connections: 3,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let streams = futures::future::join_all((0..20).map(|_| pool.open_stream())).await;
+        assert!(streams.iter().all(Result::is_ok));
+        assert_eq!(server.accepted(), 3);
+        assert_eq!(pool.connections.lock().unwrap().len(), 3);
+        assert_eq!(pool.connecting.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn failed_connect_releases_reservation() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                max_connections: 2,
+                max_reconnect_attempts: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let busy = pool.open_stream().await.unwrap();
+        server.refuse_next(1);
+        assert!(pool.open_stream().await.is_err());
+        assert_eq!(pool.connecting.load(Ordering::Relaxed), 0);
+        let stream = pool.open_stream().await.unwrap();
+        assert!(!on_same_connection(&busy, &stream));
+        assert_eq!(server.accepted(), 2);
+    }
+
+    #[tokio::test]
+    async fn respects_max_connections() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                max_connections: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut streams = Vec::new();
+        for _ in 0..5 {
+            streams.push(pool.open_stream().await.unwrap());
+        }
+        assert_eq!(server.accepted(), 2);
+        let mut loads: Vec<usize> = pool
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(Connection::load)
+            .collect();
+        loads.sort();
+        assert_eq!(loads, [2, 3]);
+
+        drop(streams);
+        assert!(pool
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|c| c.load() == 0));
+
+        let invalid = PoolConfig {
+            min_connections: 3,
+            max_connections: 2,
+            ..Default::default()
+        };
+        let err = Pool::new(server.url().to_string(), None, invalid).await;
+        assert!(matches!(err, Err(Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_health_check_keeps_live_connection() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                health_check_interval: Some(Duration::ZERO),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let transaction = pool.open_stream().await.unwrap();
+        server.fail_open_streams(true);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert!(pool.open_stream().await.is_err());
+        assert!(pool.is_connected(&transaction));
+
+        server.fail_open_streams(false);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        let stream = pool.open_stream().await.unwrap();
+        assert!(on_same_connection(&transaction, &stream));
+        assert_eq!(server.accepted(), 1);
+    }
+
+    #[tokio::test]
+    async fn replaces_dropped_connection() {
+        let server = TestServer::start().await;
+        let pool = pool(&server, PoolConfig::default()).await;
+
+        let stream = pool.open_stream().await.unwrap();
+        server.drop_connections();
+        while pool.is_connected(&stream) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let stream = pool.open_stream().await.unwrap();
+        stream.wait_for_open().await.unwrap();
+        assert!(pool.is_connected(&stream));
+        assert_eq!(server.accepted(), 2);
+    }
+
+    #[tokio::test]
+    async fn reconnects_with_backoff() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                max_reconnect_attempts: 3,
+                reconnect_backoff: Duration::from_millis(20),
+                ..Default::default()
+            },
+        )
+        .await;
+        let stream = pool.open_stream().await.unwrap();
+
+        server.refuse_next(2);
+        let start = Instant::now();
+        pool.reconnect().await.unwrap();
+        // Waited 20ms after the first failed attempt, then 40ms after the second one
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!((server.refused(), server.accepted()), (2, 2));
+        assert!(!pool.is_connected(&stream));
+
+        server.refuse_next(3);
+        assert!(pool.reconnect().await.is_err());
+        assert_eq!(server.refused(), 5);
+        assert!(pool.connections.lock().unwrap().is_empty());
+
+        // The next stream opens a new connection
+        pool.open_stream().await.unwrap();
+        assert_eq!(server.accepted(), 3);
+    }
+
+    #[tokio::test]
+    async fn evicts_idle_connections() {
+        let server = TestServer::start().await;
+        let pool = pool(
+            &server,
+            PoolConfig {
+                min_connections: 1,
+                max_connections: 3,
+                idle_timeout: Some(Duration::from_millis(20)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let streams: Vec<_> = futures::future::try_join_all((0..3).map(|_| pool.open_stream()))
+            .await
+            .unwrap();
+        assert_eq!(pool.connections.lock().unwrap().len(), 3);
+        drop(streams);
+
+        // Busy or recently used connections are kept
+        let _stream = pool.open_stream().await.unwrap();
+        assert_eq!(pool.connections.lock().unwrap().len(), 3);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let _other = pool.open_stream().await.unwrap();
+        // The connection of `_stream` is busy, the two others were idle for too long,
+        // one of them is closed and the other one is used for the new stream
+        assert_eq!(pool.connections.lock().unwrap().len(), 2);
+    }
+}
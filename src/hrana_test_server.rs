@@ -0,0 +1,164 @@
+//! Minimal hrana server used by the tests of the WebSocket backend.
+//!
+//! Statements are executed on a single in-memory database of the local backend, shared by
+//! all connections and streams, see [test_db](crate::test_db). The server can be told to
+//! refuse connections, to fail opening streams and to drop its connections, to exercise
+//! the pool and reconnection logic.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value as Json};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::test_db;
+
+pub(crate) struct TestServer {
+    url: String,
+    state: Arc<State>,
+}
+
+struct State {
+    db: Mutex<crate::local::Client>,
+    accepted: AtomicUsize,
+    refused: AtomicUsize,
+    refuse_next: AtomicUsize,
+    fail_open_streams: AtomicBool,
+    drop_on_commit: AtomicBool,
+    kill: broadcast::Sender<()>,
+}
+
+impl TestServer {
+    /// Starts a server listening on a random local port.
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let state = Arc::new(State {
+            db: Mutex::new(crate::local::Client::in_memory().unwrap()),
+            accepted: AtomicUsize::new(0),
+            refused: AtomicUsize::new(0),
+            refuse_next: AtomicUsize::new(0),
+            fail_open_streams: AtomicBool::new(false),
+            drop_on_commit: AtomicBool::new(false),
+            kill: broadcast::channel(1).0,
+        });
+        let accepting = state.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let refuse = accepting
+                    .refuse_next
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+                if refuse {
+                    accepting.refused.fetch_add(1, Ordering::SeqCst);
+                    continue;
+                }
+                accepting.accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(socket, accepting.clone()));
+            }
+        });
+        Self { url, state }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Number of WebSocket connections accepted so far
+    pub(crate) fn accepted(&self) -> usize {
+        self.state.accepted.load(Ordering::SeqCst)
+    }
+
+    /// Number of connection attempts refused so far
+    pub(crate) fn refused(&self) -> usize {
+        self.state.refused.load(Ordering::SeqCst)
+    }
+
+    /// Closes the next `n` incoming connections before the WebSocket handshake.
+    pub(crate) fn refuse_next(&self, n: usize) {
+        self.state.refuse_next.store(n, Ordering::SeqCst);
+    }
+
+    /// Answers `open_stream` requests with an error while `fail` is true.
+    pub(crate) fn fail_open_streams(&self, fail: bool) {
+        self.state.fail_open_streams.store(fail, Ordering::SeqCst);
+    }
+
+    /// Executes COMMIT, then drops its connection instead of answering while `drop` is true,
+    /// like a network failure after the transaction was applied.
+    pub(crate) fn drop_on_commit(&self, drop: bool) {
+        self.state.drop_on_commit.store(drop, Ordering::SeqCst);
+    }
+
+    /// Drops all open connections without a closing handshake, like a network failure.
+    pub(crate) fn drop_connections(&self) {
+        self.state.kill.send(()).ok();
+    }
+}
+
+async fn serve(socket: TcpStream, state: Arc<State>) {
+    let mut kill = state.kill.subscribe();
+    let Ok(mut ws) = tokio_tungstenite::accept_async(socket).await else {
+        return;
+    };
+    loop {
+        let msg = tokio::select! {
+            msg = ws.next() => msg,
+            _ = kill.recv() => return,
+        };
+        let text = match msg {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+            _ => return,
+        };
+        let msg: Json = serde_json::from_str(&text).unwrap();
+        let reply = match msg["type"].as_str() {
+            Some("hello") => json!({"type": "hello_ok"}),
+            Some("request") => {
+                let request_id = msg["request_id"].clone();
+                let result = handle_request(&state, &msg["request"]);
+                if msg["request"]["stmt"]["sql"] == "COMMIT"
+                    && state.drop_on_commit.load(Ordering::SeqCst)
+                {
+                    return;
+                }
+                match result {
+                    Ok(response) => {
+                        json!({"type": "response_ok", "request_id": request_id, "response": response})
+                    }
+                    Err(message) => json!({
+                        "type": "response_error",
+                        "request_id": request_id,
+                        "error": {"message": message},
+                    }),
+                }
+            }
+            other => panic!("unexpected client message {other:?}"),
+        };
+        if ws.send(Message::Text(reply.to_string())).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn handle_request(state: &State, request: &Json) -> Result<Json, String> {
+    match request["type"].as_str() {
+        Some("open_stream") if state.fail_open_streams.load(Ordering::SeqCst) => {
+            Err("cannot open stream".into())
+        }
+        Some("open_stream") => Ok(json!({"type": "open_stream"})),
+        Some("close_stream") => Ok(json!({"type": "close_stream"})),
+        Some("execute") => {
+            let result = test_db::execute(&state.db.lock().unwrap(), &request["stmt"])?;
+            Ok(json!({"type": "execute", "result": result}))
+        }
+        Some("batch") => {
+            let result = test_db::batch(&state.db.lock().unwrap(), &request["batch"]);
+            Ok(json!({"type": "batch", "result": result}))
+        }
+        other => panic!("unexpected request {other:?}"),
+    }
+}
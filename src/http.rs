@@ -1,9 +1,16 @@
-use crate::client::Config;
-use anyhow::Result;
+use crate::client::{Config, RetryPolicy, Timeouts};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use crate::{proto::pipeline, BatchResult, ResultSet, Statement};
+use futures::StreamExt;
+
+use crate::proto::{cursor, pipeline};
+use crate::{BatchResult, Error, PreparedStatement, Result, ResultSet, Row, RowStream, Statement};
+
+/// Body of a streamed HTTP response, in chunks as they arrive.
+pub type BodyStream = futures::stream::BoxStream<'static, Result<Vec<u8>>>;
 
 /// Information about the current session: the server-generated cookie
 /// and the URL that should be used for further communication.
@@ -11,6 +18,94 @@ use crate::{proto::pipeline, BatchResult, ResultSet, Statement};
 struct Cookie {
     baton: Option<String>,
     base_url: Option<String>,
+    stored_sql: StoredSql,
+    /// The server closed the stream, so the transaction is lost and nothing more
+    /// can be sent on it.
+    closed: bool,
+}
+
+/// Most SQL texts kept stored on the stream of a transaction, below the limit of sqld.
+const MAX_STORED_SQL: usize = 128;
+
+/// Key of the stream kept open to execute prepared statements outside of transactions,
+/// among the streams of transactions, whose ids start at 1 and never reach it.
+const PREPARED_STREAM: u64 = u64::MAX;
+
+/// SQL of prepared statements stored on the stream of a transaction, so that executing
+/// them again only sends the id of their SQL.
+#[derive(Clone, Debug, Default)]
+struct StoredSql {
+    /// SQL texts and their ids, least recently used first
+    ids: Vec<(String, i32)>,
+    next_id: i32,
+}
+
+impl StoredSql {
+    /// Returns the id of `sql`, pushing to `setup` the requests storing it if needed.
+    /// Once the stream holds [MAX_STORED_SQL] texts, the least recently used one which is
+    /// not in `used` is closed. If there is none, returns `None` and `sql` is not stored.
+    fn id(
+        &mut self,
+        sql: &str,
+        used: &[i32],
+        setup: &mut Vec<pipeline::StreamRequest>,
+    ) -> Option<i32> {
+        if let Some(pos) = self.ids.iter().position(|(stored, _)| stored == sql) {
+            let entry = self.ids.remove(pos);
+            let sql_id = entry.1;
+            self.ids.push(entry);
+            return Some(sql_id);
+        }
+        if self.ids.len() >= MAX_STORED_SQL {
+            let pos = self.ids.iter().position(|(_, id)| !used.contains(id))?;
+            let (_, sql_id) = self.ids.remove(pos);
+            setup.push(pipeline::StreamRequest::CloseSql(pipeline::CloseSqlReq {
+                sql_id,
+            }));
+        }
+        let sql_id = self.next_id;
+        self.next_id += 1;
+        setup.push(pipeline::StreamRequest::StoreSql(pipeline::StoreSqlReq {
+            sql_id,
+            sql: sql.to_string(),
+        }));
+        self.ids.push((sql.to_string(), sql_id));
+        Some(sql_id)
+    }
+
+    /// Forgets SQL which the server failed to store
+    fn forget(&mut self, sql_id: i32) {
+        self.ids.retain(|(_, id)| *id != sql_id);
+    }
+}
+
+/// Converts the statements of a request sent on a stream. Prepared statements sent on
+/// a stream kept open, of a transaction or the [PREPARED_STREAM], refer to their SQL
+/// stored on it.
+struct StmtEncoder<'a> {
+    stored: Option<&'a mut StoredSql>,
+    /// `store_sql` and `close_sql` requests to send before the statements
+    setup: Vec<pipeline::StreamRequest>,
+    /// Ids of the stored SQL used by the request
+    used: Vec<i32>,
+}
+
+impl StmtEncoder<'_> {
+    fn stmt(&mut self, stmt: Statement) -> crate::proto::Stmt {
+        let sql_id = match self.stored.as_deref_mut() {
+            Some(stored) if stmt.prepared => stored.id(&stmt.sql, &self.used, &mut self.setup),
+            _ => None,
+        };
+        let Some(sql_id) = sql_id else {
+            return Client::into_hrana(stmt);
+        };
+        self.used.push(sql_id);
+        Client::bind_args(
+            crate::proto::Stmt::stored(sql_id, true),
+            stmt.args,
+            stmt.named_args,
+        )
+    }
 }
 
 /// Generic HTTP client. Needs a helper function that actually sends
@@ -19,8 +114,18 @@ struct Cookie {
 pub struct Client {
     inner: InnerClient,
     cookies: Arc<RwLock<HashMap<u64, Cookie>>>,
+    base_url: String,
     url_for_queries: String,
+    /// Pipeline URL of the read replica serving read-only transactions
+    replica_url_for_queries: Option<String>,
     auth: String,
+    retry: RetryPolicy,
+    timeouts: Timeouts,
+    /// Set once the server answered that it does not support cursors (Hrana 3)
+    cursors_unsupported: Arc<AtomicBool>,
+    /// Held while a request is sent on the [PREPARED_STREAM], as the requests of
+    /// a stream must follow each other
+    prepared_stream: Arc<futures::lock::Mutex<()>>,
 }
 
 #[derive(Clone, Debug)]
@@ -40,17 +145,145 @@ impl InnerClient {
         url: String,
         auth: String,
         body: String,
+        timeout: Option<Duration>,
     ) -> Result<pipeline::ServerMsg> {
         match self {
             #[cfg(feature = "reqwest_backend")]
-            InnerClient::Reqwest(client) => client.send(url, auth, body).await,
+            InnerClient::Reqwest(client) => client.send(url, auth, body, timeout).await,
+            #[cfg(feature = "workers_backend")]
+            InnerClient::Workers(client) => client.send(url, auth, body, timeout).await,
+            #[cfg(feature = "spin_backend")]
+            InnerClient::Spin(client) => client.send(url, auth, body, timeout).await,
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
+    /// Like [InnerClient::send], but returns the response body as it arrives.
+    pub async fn send_streaming(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+    ) -> Result<BodyStream> {
+        match self {
+            #[cfg(feature = "reqwest_backend")]
+            InnerClient::Reqwest(client) => client.send_streaming(url, auth, body).await,
             #[cfg(feature = "workers_backend")]
-            InnerClient::Workers(client) => client.send(url, auth, body).await,
+            InnerClient::Workers(client) => client.send_streaming(url, auth, body).await,
             #[cfg(feature = "spin_backend")]
-            InnerClient::Spin(client) => client.send(url, auth, body).await,
+            InnerClient::Spin(client) => client.send_streaming(url, auth, body).await,
             _ => panic!("Must enable at least one feature"),
         }
     }
+
+    /// False if this client cannot wait without blocking, in which case failed
+    /// requests are not retried and their error is returned right away.
+    pub fn can_sleep(&self) -> bool {
+        match self {
+            // Spin has no timer, and sleeping would stall the whole component
+            #[cfg(feature = "spin_backend")]
+            InnerClient::Spin(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Waits for `duration` before the next attempt of a failed request.
+    /// Returns immediately if the client [cannot sleep](InnerClient::can_sleep).
+    pub async fn sleep(&self, duration: Duration) {
+        match self {
+            #[cfg(feature = "reqwest_backend")]
+            InnerClient::Reqwest(_) => tokio::time::sleep(duration).await,
+            #[cfg(feature = "workers_backend")]
+            InnerClient::Workers(_) => worker::Delay::from(duration).await,
+            #[cfg(feature = "spin_backend")]
+            InnerClient::Spin(_) => {
+                let _ = duration;
+            }
+            _ => panic!("Must enable at least one feature"),
+        }
+    }
+
+    /// Returns the current time as a duration since the Unix epoch.
+    pub fn now(&self) -> Duration {
+        match self {
+            // std::time is not available in Workers
+            #[cfg(feature = "workers_backend")]
+            InnerClient::Workers(_) => Duration::from_millis(worker::Date::now().as_millis()),
+            _ => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// True if the request failed before the server could process it, or the server
+/// reported a temporary condition, so that repeating it may succeed.
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::Transport(_) | Error::Timeout(_) => true,
+        Error::Http { status, .. } => *status >= 500 || *status == 429,
+        _ => false,
+    }
+}
+
+/// Reads a cursor response, one line at a time.
+struct CursorBody {
+    body: BodyStream,
+    buffer: Vec<u8>,
+}
+
+impl CursorBody {
+    async fn next_line(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+                let rest = self.buffer.split_off(end + 1);
+                let mut line = std::mem::replace(&mut self.buffer, rest);
+                line.pop();
+                return Ok(Some(line));
+            }
+            match self.body.next().await {
+                Some(chunk) => self.buffer.extend(chunk?),
+                None if self.buffer.is_empty() => return Ok(None),
+                None => return Ok(Some(std::mem::take(&mut self.buffer))),
+            }
+        }
+    }
+
+    async fn next_entry(&mut self) -> Result<Option<cursor::CursorEntry>> {
+        match self.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_slice(&line)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Stream a cursor was opened on, closed once the cursor is done with it or dropped,
+/// so that it does not stay open on the server until it expires.
+///
+/// The stream is closed in the background when running inside a Tokio runtime,
+/// otherwise it is left for the server to expire.
+struct CursorStream {
+    #[cfg(feature = "reqwest_backend")]
+    client: Client,
+    baton: Option<String>,
+    #[cfg(feature = "reqwest_backend")]
+    base_url: Option<String>,
+}
+
+impl Drop for CursorStream {
+    fn drop(&mut self) {
+        let Some(baton) = self.baton.take() else {
+            return;
+        };
+        #[cfg(feature = "reqwest_backend")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let client = self.client.clone();
+            let base_url = self.base_url.take();
+            runtime.spawn(async move { client.close_cursor_stream(baton, base_url).await.ok() });
+            return;
+        }
+        tracing::debug!("Leaving the stream of cursor {baton} to expire");
+    }
 }
 
 impl Client {
@@ -72,23 +305,54 @@ impl Client {
         Self {
             inner,
             cookies: Arc::new(RwLock::new(HashMap::new())),
+            base_url,
             url_for_queries,
+            replica_url_for_queries: None,
             auth: format!("Bearer {token}"),
+            retry: RetryPolicy::default(),
+            timeouts: Timeouts::default(),
+            cursors_unsupported: Arc::new(AtomicBool::new(false)),
+            prepared_stream: Arc::new(futures::lock::Mutex::new(())),
         }
     }
 
     /// Establishes  a database client from a `Config` object
-    pub fn from_config(inner: InnerClient, config: Config) -> anyhow::Result<Self> {
-        Ok(Self::new(
-            inner,
-            config.url,
-            config.auth_token.unwrap_or_default(),
-        ))
+    pub fn from_config(inner: InnerClient, config: Config) -> Result<Self> {
+        let mut client = Self::new(inner, config.url, config.auth_token.unwrap_or_default())
+            .with_retry_policy(config.retry)
+            .with_timeouts(config.timeouts);
+        client.replica_url_for_queries = config.read_replica.map(|url| format!("{url}v2/pipeline"));
+        Ok(client)
+    }
+
+    /// Sends the requests of the transaction `tx_id` to the read replica, if there is one.
+    pub fn use_read_replica(&self, tx_id: u64) {
+        if let Some(url) = &self.replica_url_for_queries {
+            self.cookies.write().unwrap().insert(
+                tx_id,
+                Cookie {
+                    base_url: Some(url.clone()),
+                    ..Cookie::default()
+                },
+            );
+        }
     }
 
-    pub fn from_env(inner: InnerClient) -> anyhow::Result<Client> {
+    /// Sets the policy for retrying requests that are safe to repeat, see [RetryPolicy]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the timeouts of requests and statements, see [Timeouts]
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn from_env(inner: InnerClient) -> Result<Client> {
         let url = std::env::var("LIBSQL_CLIENT_URL").map_err(|_| {
-            anyhow::anyhow!("LIBSQL_CLIENT_URL variable should point to your sqld database")
+            Error::Config("LIBSQL_CLIENT_URL variable should point to your sqld database".into())
         })?;
 
         let token = std::env::var("LIBSQL_CLIENT_TOKEN").unwrap_or_default();
@@ -98,22 +362,116 @@ impl Client {
 
 impl Client {
     fn into_hrana(stmt: Statement) -> crate::proto::Stmt {
-        let mut hrana_stmt = crate::proto::Stmt::new(stmt.sql, true);
-        for param in stmt.args {
+        Self::bind_args(
+            crate::proto::Stmt::new(stmt.sql, true),
+            stmt.args,
+            stmt.named_args,
+        )
+    }
+
+    fn bind_args(
+        mut hrana_stmt: crate::proto::Stmt,
+        args: Vec<crate::Value>,
+        named_args: Vec<(String, crate::Value)>,
+    ) -> crate::proto::Stmt {
+        for param in args {
             hrana_stmt.bind(param);
         }
+        for (name, value) in named_args {
+            hrana_stmt.bind_named(name, value);
+        }
         hrana_stmt
     }
 
+    /// True if sending the statement twice has the same effect as sending it once.
+    fn is_retryable(stmt: &Statement) -> bool {
+        stmt.idempotent || crate::utils::is_read_only(&stmt.sql)
+    }
+
+    /// Sends `body` to `url`. If `retryable` is set, transient failures are retried
+    /// according to the retry policy. All attempts together are limited by the
+    /// statement timeout.
+    async fn send(
+        &self,
+        url: String,
+        body: String,
+        retryable: bool,
+    ) -> Result<pipeline::ServerMsg> {
+        let max_attempts = if retryable && self.inner.can_sleep() {
+            self.retry.max_attempts
+        } else {
+            1
+        };
+        let deadline = self.timeouts.statement.map(|t| self.inner.now() + t);
+        let mut delay = self.retry.backoff.min(self.retry.max_backoff);
+        let mut attempt = 1;
+        loop {
+            // Each attempt may only use the time left until the deadline
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_sub(self.inner.now());
+                    if remaining.is_zero() {
+                        return Err(Error::Timeout(format!(
+                            "statement did not complete in {:?}",
+                            self.timeouts.statement.unwrap_or_default()
+                        )));
+                    }
+                    Some(
+                        self.timeouts
+                            .request
+                            .map_or(remaining, |t| t.min(remaining)),
+                    )
+                }
+                None => self.timeouts.request,
+            };
+            match self
+                .inner
+                .send(url.clone(), self.auth.clone(), body.clone(), timeout)
+                .await
+            {
+                Err(e) if attempt < max_attempts && is_transient(&e) => {
+                    let wait = self.retry.wait(delay);
+                    if deadline.is_some_and(|deadline| self.inner.now() + wait >= deadline) {
+                        return Err(e);
+                    }
+                    tracing::warn!(
+                        "Request to {url} failed (attempt {attempt}), retrying in {wait:?}: {e}"
+                    );
+                    self.inner.sleep(wait).await;
+                    delay = (delay * 2).min(self.retry.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
     pub async fn raw_batch(
         &self,
         stmts: impl IntoIterator<Item = impl Into<Statement>>,
-    ) -> anyhow::Result<BatchResult> {
+    ) -> Result<BatchResult> {
         let mut batch = crate::proto::Batch::new();
+        let mut retryable = true;
         for stmt in stmts.into_iter() {
-            batch.step(None, Self::into_hrana(stmt.into()));
+            let stmt = stmt.into();
+            retryable &= Self::is_retryable(&stmt);
+            batch.step(None, Self::into_hrana(stmt));
+        }
+        self.send_batch(batch, retryable).await
+    }
+
+    /// Executes a batch whose steps may be conditional, the server evaluating the conditions.
+    pub async fn execute_batch(&self, batch: crate::BatchBuilder) -> Result<BatchResult> {
+        let mut steps = crate::proto::Batch::new();
+        let mut retryable = true;
+        for (cond, stmt) in batch.into_steps() {
+            retryable &= Self::is_retryable(&stmt);
+            steps.step(cond, Self::into_hrana(stmt));
         }
+        self.send_batch(steps, retryable).await
+    }
 
+    async fn send_batch(&self, batch: crate::proto::Batch, retryable: bool) -> Result<BatchResult> {
         let msg = pipeline::ClientMsg {
             baton: None,
             requests: vec![
@@ -123,44 +481,46 @@ impl Client {
         };
         let body = serde_json::to_string(&msg)?;
         let mut response: pipeline::ServerMsg = self
-            .inner
-            .send(self.url_for_queries.clone(), self.auth.clone(), body)
+            .send(self.url_for_queries.clone(), body, retryable)
             .await?;
 
         if response.results.is_empty() {
-            anyhow::bail!(
+            return Err(Error::Protocol(format!(
                 "Unexpected empty response from server: {:?}",
                 response.results
-            );
+            )));
         }
         if response.results.len() > 2 {
             // One with actual results, one closing the stream
-            anyhow::bail!(
+            return Err(Error::Protocol(format!(
                 "Unexpected multiple responses from server: {:?}",
                 response.results
-            );
+            )));
         }
         match response.results.swap_remove(0) {
             pipeline::Response::Ok(pipeline::StreamResponseOk {
                 response: pipeline::StreamResponse::Batch(batch_result),
             }) => Ok(batch_result.result),
-            pipeline::Response::Ok(_) => {
-                anyhow::bail!("Unexpected response from server: {:?}", response.results)
-            }
-            pipeline::Response::Error(e) => {
-                anyhow::bail!("Error from server: {:?}", e)
-            }
+            pipeline::Response::Ok(_) => Err(Error::Protocol(format!(
+                "Unexpected response from server: {:?}",
+                response.results
+            ))),
+            pipeline::Response::Error(e) => Err(e.error.into()),
         }
     }
 
-    async fn execute_inner(
+    /// Sends the requests built by `requests` on the stream of transaction `tx_id`, or on
+    /// a new stream if `tx_id` is 0, and keeps the baton returned for the transaction.
+    ///
+    /// The requests storing SQL for prepared statements are sent first, and their
+    /// responses are removed from the returned message.
+    async fn send_requests(
         &self,
-        stmt: impl Into<Statement> + Send,
         tx_id: u64,
-    ) -> Result<ResultSet> {
-        let stmt = Self::into_hrana(stmt.into());
-
-        let cookie = if tx_id > 0 {
+        retryable: bool,
+        requests: impl FnOnce(&mut StmtEncoder) -> Vec<pipeline::StreamRequest>,
+    ) -> Result<pipeline::ServerMsg> {
+        let mut cookie = if tx_id > 0 {
             self.cookies
                 .read()
                 .unwrap()
@@ -170,67 +530,257 @@ impl Client {
         } else {
             Cookie::default()
         };
-        let requests = if tx_id != 0 {
-            vec![pipeline::StreamRequest::Execute(
-                pipeline::StreamExecuteReq { stmt },
-            )]
-        } else {
-            vec![
-                pipeline::StreamRequest::Execute(pipeline::StreamExecuteReq { stmt }),
-                pipeline::StreamRequest::Close,
-            ]
+        if cookie.closed {
+            return Err(Error::StreamExpired(
+                "Stream closed: server returned empty baton".into(),
+            ));
+        }
+        let mut encoder = StmtEncoder {
+            stored: (tx_id > 0).then_some(&mut cookie.stored_sql),
+            setup: Vec::new(),
+            used: Vec::new(),
         };
+        let requests = requests(&mut encoder);
+        let setup = encoder.setup;
+        let stored_ids: Vec<Option<i32>> = setup
+            .iter()
+            .map(|request| match request {
+                pipeline::StreamRequest::StoreSql(req) => Some(req.sql_id),
+                _ => None,
+            })
+            .collect();
         let msg = pipeline::ClientMsg {
-            baton: cookie.baton,
-            requests,
+            baton: cookie.baton.take(),
+            requests: setup.into_iter().chain(requests).collect(),
         };
         let body = serde_json::to_string(&msg)?;
         let url = cookie
             .base_url
+            .take()
             .unwrap_or_else(|| self.url_for_queries.clone());
-        let mut response: pipeline::ServerMsg =
-            self.inner.send(url, self.auth.clone(), body).await?;
+        let mut response: pipeline::ServerMsg = self.send(url.clone(), body, retryable).await?;
 
+        let setup_len = stored_ids.len().min(response.results.len());
+        let mut error = None;
+        for (sql_id, result) in stored_ids
+            .into_iter()
+            .zip(response.results.drain(..setup_len))
+        {
+            if let pipeline::Response::Error(e) = result {
+                if let Some(sql_id) = sql_id {
+                    cookie.stored_sql.forget(sql_id);
+                }
+                error.get_or_insert(e.error);
+            }
+        }
         if tx_id > 0 {
-            let base_url = response.base_url;
-            match response.baton {
+            match response.baton.take() {
                 Some(baton) => {
                     self.cookies.write().unwrap().insert(
                         tx_id,
                         Cookie {
                             baton: Some(baton),
-                            base_url,
+                            // The stream stays on the server it was opened on, like a replica
+                            base_url: response.base_url.take().or(Some(url)),
+                            stored_sql: cookie.stored_sql,
+                            closed: false,
+                        },
+                    );
+                }
+                // The results of this request are still valid, e.g. a COMMIT was applied,
+                // so the lost stream is only reported by the next request.
+                None => {
+                    self.cookies.write().unwrap().insert(
+                        tx_id,
+                        Cookie {
+                            closed: true,
+                            ..Cookie::default()
                         },
                     );
                 }
-                None => anyhow::bail!("Stream closed: server returned empty baton"),
             }
         }
+        if let Some(error) = error {
+            return Err(error.into());
+        }
+        Ok(response)
+    }
+
+    async fn execute_inner(
+        &self,
+        stmt: impl Into<Statement> + Send,
+        tx_id: u64,
+    ) -> Result<ResultSet> {
+        let stmt = stmt.into();
+        // A statement bound to a baton is never retried, because the server may have
+        // already executed it and moved the transaction forward
+        let retryable = tx_id == 0 && Self::is_retryable(&stmt);
+
+        let mut response = self
+            .send_requests(tx_id, retryable, |encoder| {
+                let stmt = encoder.stmt(stmt);
+                if tx_id != 0 {
+                    vec![pipeline::StreamRequest::Execute(
+                        pipeline::StreamExecuteReq { stmt },
+                    )]
+                } else {
+                    vec![
+                        pipeline::StreamRequest::Execute(pipeline::StreamExecuteReq { stmt }),
+                        pipeline::StreamRequest::Close,
+                    ]
+                }
+            })
+            .await?;
 
         if response.results.is_empty() {
-            anyhow::bail!(
+            return Err(Error::Protocol(format!(
                 "Unexpected empty response from server: {:?}",
                 response.results
-            );
+            )));
         }
         if response.results.len() > 2 {
             // One with actual results, one closing the stream
-            anyhow::bail!(
+            return Err(Error::Protocol(format!(
                 "Unexpected multiple responses from server: {:?}",
                 response.results
-            );
+            )));
         }
         match response.results.swap_remove(0) {
             pipeline::Response::Ok(pipeline::StreamResponseOk {
                 response: pipeline::StreamResponse::Execute(execute_result),
             }) => Ok(ResultSet::from(execute_result.result)),
-            pipeline::Response::Ok(_) => {
-                anyhow::bail!("Unexpected response from server: {:?}", response.results)
+            pipeline::Response::Ok(_) => Err(Error::Protocol(format!(
+                "Unexpected response from server: {:?}",
+                response.results
+            ))),
+            pipeline::Response::Error(e) => Err(e.error.into()),
+        }
+    }
+
+    /// Creates a [PreparedStatement].
+    ///
+    /// Statements created from it store their SQL on the stream they are executed on the
+    /// first time, and then only send its id. In a transaction, that is the stream of the
+    /// transaction. Outside of transactions, it is a stream kept open for prepared
+    /// statements, which are sent one after the other on it.
+    ///
+    /// If that stream expired, e.g. because it was not used for a while, the statement is
+    /// sent with its full SQL on a new stream, and the next one opens a new stream to store
+    /// its SQL on. Statements executed in batches always send their full SQL.
+    pub fn prepare(&self, sql: impl Into<String>) -> Result<PreparedStatement> {
+        Ok(PreparedStatement::new(sql.into()))
+    }
+
+    /// Executes a statement with a cursor, so that its rows are received while
+    /// the server produces them instead of in a single response.
+    ///
+    /// Cursors need a server supporting version 3 of the Hrana protocol. Older servers,
+    /// which answer `404 Not Found`, get the statement executed normally instead,
+    /// and all its rows are received at once before being yielded.
+    ///
+    /// Timeouts do not apply, as reading a large result may legitimately take long.
+    pub async fn query_stream(&self, stmt: impl Into<Statement> + Send) -> Result<RowStream> {
+        let stmt = stmt.into();
+        if self.cursors_unsupported.load(Ordering::Relaxed) {
+            return self.query_stream_without_cursor(stmt).await;
+        }
+        let mut batch = crate::proto::Batch::new();
+        batch.step(None, Self::into_hrana(stmt.clone()));
+        let body = serde_json::to_string(&cursor::CursorReq { baton: None, batch })?;
+        let body = match self
+            .inner
+            .send_streaming(
+                format!("{}v3/cursor", self.base_url),
+                self.auth.clone(),
+                body,
+            )
+            .await
+        {
+            Ok(body) => body,
+            Err(Error::Http { status: 404, .. }) => {
+                tracing::warn!(
+                    "{} does not support cursors (Hrana 3), rows will be received all at once",
+                    self.base_url
+                );
+                self.cursors_unsupported.store(true, Ordering::Relaxed);
+                return self.query_stream_without_cursor(stmt).await;
             }
-            pipeline::Response::Error(e) => {
-                anyhow::bail!("Error from server: {:?}", e)
+            Err(e) => return Err(e),
+        };
+        let mut cursor = CursorBody {
+            body,
+            buffer: Vec::new(),
+        };
+        // The first line carries the baton of the stream the cursor was opened on
+        let first: cursor::CursorRespBody = match cursor.next_line().await? {
+            Some(line) => serde_json::from_slice(&line)?,
+            None => {
+                return Err(Error::Protocol(
+                    "Unexpected empty response from server".into(),
+                ))
             }
-        }
+        };
+        let stream = CursorStream {
+            #[cfg(feature = "reqwest_backend")]
+            client: self.clone(),
+            baton: first.baton,
+            #[cfg(feature = "reqwest_backend")]
+            base_url: first.base_url,
+        };
+
+        let columns: Vec<String> = Vec::new();
+        let state = Some((cursor, columns, stream));
+        let rows = futures::stream::unfold(state, |state| async move {
+            let (mut cursor, mut columns, stream) = state?;
+            loop {
+                let entry = match cursor.next_entry().await {
+                    Ok(Some(entry)) => entry,
+                    // Dropping the state closes the stream
+                    Ok(None) => return None,
+                    Err(e) => return Some((Err(e), None)),
+                };
+                match entry {
+                    cursor::CursorEntry::StepBegin(begin) => {
+                        columns = begin
+                            .cols
+                            .into_iter()
+                            .map(|c| c.name.unwrap_or_default())
+                            .collect();
+                    }
+                    cursor::CursorEntry::Row(row) => {
+                        let row = Row::from_values(&columns, row.row);
+                        return Some((Ok(row), Some((cursor, columns, stream))));
+                    }
+                    cursor::CursorEntry::StepEnd(_) => {}
+                    cursor::CursorEntry::StepError(cursor::StepErrorEntry { error, .. })
+                    | cursor::CursorEntry::Error(cursor::ErrorEntry { error }) => {
+                        return Some((Err(error.into()), None))
+                    }
+                }
+            }
+        });
+        Ok(rows.boxed())
+    }
+
+    async fn query_stream_without_cursor(&self, stmt: Statement) -> Result<RowStream> {
+        let rows = self.execute(stmt).await?.rows;
+        Ok(futures::stream::iter(rows.into_iter().map(Ok)).boxed())
+    }
+
+    // Closes the stream of a cursor, which was opened with the Hrana 3 protocol.
+    #[cfg(feature = "reqwest_backend")]
+    async fn close_cursor_stream(&self, baton: String, base_url: Option<String>) -> Result<()> {
+        let msg = pipeline::ClientMsg {
+            baton: Some(baton),
+            requests: vec![pipeline::StreamRequest::Close],
+        };
+        let base_url = base_url.as_deref().unwrap_or(&self.base_url);
+        let url = format!("{}/v3/pipeline", base_url.trim_end_matches('/'));
+        let body = serde_json::to_string(&msg)?;
+        self.inner
+            .send(url, self.auth.clone(), body, self.timeouts.request)
+            .await
+            .map(|_| ())
     }
 
     async fn close_stream_for(&self, tx_id: u64) -> Result<()> {
@@ -241,6 +791,10 @@ impl Client {
             .get(&tx_id)
             .cloned()
             .unwrap_or_default();
+        if cookie.closed {
+            self.cookies.write().unwrap().remove(&tx_id);
+            return Ok(());
+        }
         let msg = pipeline::ClientMsg {
             baton: cookie.baton,
             requests: vec![pipeline::StreamRequest::Close],
@@ -249,7 +803,10 @@ impl Client {
             .base_url
             .unwrap_or_else(|| self.url_for_queries.clone());
         let body = serde_json::to_string(&msg)?;
-        self.inner.send(url, self.auth.clone(), body).await.ok();
+        self.inner
+            .send(url, self.auth.clone(), body, self.timeouts.request)
+            .await
+            .ok();
         self.cookies.write().unwrap().remove(&tx_id);
         Ok(())
     }
@@ -257,13 +814,79 @@ impl Client {
     /// # Arguments
     /// * `stmt` - the SQL statement
     pub async fn execute(&self, stmt: impl Into<Statement> + Send) -> Result<ResultSet> {
+        let stmt = stmt.into();
+        if stmt.prepared {
+            return self.execute_prepared(stmt).await;
+        }
         self.execute_inner(stmt, 0).await
     }
 
+    /// Executes a prepared statement on the [PREPARED_STREAM], see [Client::prepare].
+    async fn execute_prepared(&self, stmt: Statement) -> Result<ResultSet> {
+        let _sending = self.prepared_stream.lock().await;
+        match self.execute_inner(stmt.clone(), PREPARED_STREAM).await {
+            Ok(result) => Ok(result),
+            Err(e @ Error::Sqlite { .. }) => Err(e),
+            Err(e) => {
+                // The state of the stream is unknown, so the next statement opens a new one
+                self.cookies.write().unwrap().remove(&PREPARED_STREAM);
+                // A stream found expired was not used by the request, and statements safe
+                // to repeat may be sent again, both on a new stream of their own
+                if e.is_stream_expired() || (Self::is_retryable(&stmt) && is_transient(&e)) {
+                    tracing::debug!("Prepared statements stream lost, sending the SQL: {e}");
+                    self.execute_inner(stmt, 0).await
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
     pub async fn execute_in_transaction(&self, tx_id: u64, stmt: Statement) -> Result<ResultSet> {
         self.execute_inner(stmt, tx_id).await
     }
 
+    /// Executes statements in one request, on the stream of the transaction.
+    ///
+    /// Each statement only runs if the previous one succeeded, so the statements
+    /// following a failed one are not executed.
+    pub async fn batch_in_transaction(
+        &self,
+        tx_id: u64,
+        stmts: Vec<Statement>,
+    ) -> Result<BatchResult> {
+        let mut response = self
+            .send_requests(tx_id, false, |encoder| {
+                let mut batch = crate::proto::Batch::new();
+                for (i, stmt) in stmts.into_iter().enumerate() {
+                    let cond = i
+                        .checked_sub(1)
+                        .map(|step| crate::BatchCond::Ok { step: step as u32 });
+                    batch.step(cond, encoder.stmt(stmt));
+                }
+                vec![pipeline::StreamRequest::Batch(pipeline::StreamBatchReq {
+                    batch,
+                })]
+            })
+            .await?;
+        if response.results.len() != 1 {
+            return Err(Error::Protocol(format!(
+                "Unexpected number of responses from server: {:?}",
+                response.results
+            )));
+        }
+        match response.results.swap_remove(0) {
+            pipeline::Response::Ok(pipeline::StreamResponseOk {
+                response: pipeline::StreamResponse::Batch(batch_result),
+            }) => Ok(batch_result.result),
+            pipeline::Response::Ok(_) => Err(Error::Protocol(format!(
+                "Unexpected response from server: {:?}",
+                response.results
+            ))),
+            pipeline::Response::Error(e) => Err(e.error.into()),
+        }
+    }
+
     pub async fn commit_transaction(&self, tx_id: u64) -> Result<()> {
         self.execute_inner("COMMIT", tx_id).await.map(|_| ())?;
         self.close_stream_for(tx_id).await.ok();
@@ -275,4 +898,370 @@ impl Client {
         self.close_stream_for(tx_id).await.ok();
         Ok(())
     }
+
+    /// False if this client cannot wait before retrying a failed operation.
+    pub(crate) fn can_sleep(&self) -> bool {
+        self.inner.can_sleep()
+    }
+
+    /// Waits for `duration` before retrying a failed operation.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.inner.sleep(duration).await
+    }
+
+    /// Releases the baton of a transaction dropped without being committed or rolled back.
+    ///
+    /// Closing its stream makes the server roll the transaction back. The stream is closed
+    /// in the background when running inside a Tokio runtime, otherwise the server rolls
+    /// the transaction back once the stream expires.
+    pub fn drop_transaction(&self, tx_id: u64) {
+        #[cfg(feature = "reqwest_backend")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let client = self.clone();
+            runtime.spawn(async move { client.close_stream_for(tx_id).await.ok() });
+            return;
+        }
+        self.cookies.write().unwrap().remove(&tx_id);
+    }
+}
+
+#[cfg(all(test, feature = "reqwest_backend"))]
+mod tests {
+    use super::*;
+    use crate::http_test_server::{pipeline_response, Request, TestServer};
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    fn cursor_server() -> impl Fn(&Request) -> (u16, String) {
+        |request| match request.path.as_str() {
+            "/v3/cursor" => {
+                let lines = [
+                    json!({"baton": "cursor-baton", "base_url": null}),
+                    json!({"type": "step_begin", "step": 0, "cols": [{"name": "x"}]}),
+                    json!({"type": "row", "row": [{"type": "integer", "value": "1"}]}),
+                    json!({"type": "row", "row": [{"type": "integer", "value": "2"}]}),
+                    json!({"type": "step_end", "affected_row_count": 0, "last_insert_rowid": null}),
+                ];
+                let body: String = lines.iter().map(|l| format!("{l}\n")).collect();
+                (200, body)
+            }
+            "/v3/pipeline" => (
+                200,
+                pipeline_response(None, json!([{"type": "ok", "response": {"type": "close"}}])),
+            ),
+            path => (404, format!("unexpected path {path}")),
+        }
+    }
+
+    // Waits until the server received a request closing the stream of the cursor.
+    async fn wait_for_close(server: &TestServer) {
+        for _ in 0..100 {
+            let closed = server.requests().iter().any(|r| {
+                r.path == "/v3/pipeline"
+                    && r.body["baton"] == "cursor-baton"
+                    && r.body["requests"] == json!([{"type": "close"}])
+            });
+            if closed {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("cursor stream not closed: {:?}", server.requests());
+    }
+
+    // Answers every request of a pipeline successfully, keeping the stream open.
+    fn pipeline_server() -> impl Fn(&Request) -> (u16, String) {
+        |request| {
+            let results: Vec<serde_json::Value> = request.body["requests"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| {
+                    let response = match r["type"].as_str().unwrap() {
+                        "execute" => json!({"type": "execute", "result": {
+                            "cols": [], "rows": [], "affected_row_count": 1, "last_insert_rowid": null,
+                        }}),
+                        other => json!({ "type": other }),
+                    };
+                    json!({"type": "ok", "response": response})
+                })
+                .collect();
+            (200, pipeline_response(Some("tx-baton"), json!(results)))
+        }
+    }
+
+    #[tokio::test]
+    async fn prepared_statement_stored_on_transaction_stream() {
+        let server = TestServer::start(pipeline_server()).await;
+        let db = server.client();
+        let insert = db.prepare("INSERT INTO t VALUES (?)").unwrap();
+        db.execute_in_transaction(1, insert.bind(&[1]))
+            .await
+            .unwrap();
+        db.execute_in_transaction(1, insert.bind(&[2]))
+            .await
+            .unwrap();
+
+        let requests: Vec<serde_json::Value> = server
+            .requests()
+            .into_iter()
+            .map(|r| r.body["requests"].clone())
+            .collect();
+        let execute = |value: &str| {
+            json!({"type": "execute", "stmt": {
+                "sql_id": 0,
+                "args": [{"type": "integer", "value": value}],
+                "named_args": [],
+                "want_rows": true,
+            }})
+        };
+        assert_eq!(
+            requests[0],
+            json!([
+                {"type": "store_sql", "sql_id": 0, "sql": "INSERT INTO t VALUES (?)"},
+                execute("1"),
+            ])
+        );
+        assert_eq!(requests[1], json!([execute("2")]));
+    }
+
+    #[tokio::test]
+    async fn prepared_statement_stored_outside_transactions() {
+        let server = TestServer::start(pipeline_server()).await;
+        let db = server.client();
+        let insert = db.prepare("INSERT INTO t VALUES (?)").unwrap();
+        db.execute(insert.bind(&[1])).await.unwrap();
+        db.execute(insert.bind(&[2])).await.unwrap();
+        db.execute("INSERT INTO t VALUES (3)").await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].body["baton"], json!(null));
+        assert_eq!(requests[0].body["requests"][0]["type"], "store_sql");
+        assert_eq!(requests[0].body["requests"][1]["stmt"]["sql_id"], 0);
+        // The stream is kept for the next prepared statement
+        assert_eq!(requests[1].body["baton"], "tx-baton");
+        assert_eq!(requests[1].body["requests"].as_array().unwrap().len(), 1);
+        assert_eq!(requests[1].body["requests"][0]["stmt"]["sql_id"], 0);
+        // Other statements still get a stream of their own
+        assert_eq!(requests[2].body["baton"], json!(null));
+        assert_eq!(requests[2].body["requests"][1]["type"], "close");
+    }
+
+    #[tokio::test]
+    async fn expired_prepared_statement_stream_replaced() {
+        let answer = pipeline_server();
+        let server = TestServer::start(move |request| {
+            if request.body["baton"] == "tx-baton" {
+                let error = json!({"type": "error", "error": {
+                    "message": "stream expired", "code": "STREAM_EXPIRED",
+                }});
+                return (200, pipeline_response(None, json!([error])));
+            }
+            answer(request)
+        })
+        .await;
+        let db = server.client();
+        let select = db.prepare("SELECT ?").unwrap();
+        for i in 0..3 {
+            db.execute(select.bind(&[i])).await.unwrap();
+        }
+
+        let requests: Vec<serde_json::Value> =
+            server.requests().into_iter().map(|r| r.body).collect();
+        assert_eq!(requests.len(), 4);
+        // The statement found the stream expired, and was sent with its SQL
+        assert_eq!(requests[1]["baton"], "tx-baton");
+        assert_eq!(requests[2]["baton"], json!(null));
+        assert_eq!(requests[2]["requests"][0]["stmt"]["sql"], "SELECT ?");
+        // The next one stores its SQL on a new stream
+        assert_eq!(requests[3]["baton"], json!(null));
+        assert_eq!(requests[3]["requests"][0]["type"], "store_sql");
+    }
+
+    #[tokio::test]
+    async fn empty_baton_keeps_results_and_expires_stream() {
+        let server = TestServer::start(|request| {
+            let baton = (request.body["baton"].is_null()).then_some("tx-baton");
+            let result = json!({"type": "ok", "response": {"type": "execute", "result": {
+                "cols": [], "rows": [], "affected_row_count": 0, "last_insert_rowid": null,
+            }}});
+            (200, pipeline_response(baton, json!([result])))
+        })
+        .await;
+        let db = server.client();
+        db.execute_in_transaction(1, "BEGIN".into()).await.unwrap();
+        // The server applied COMMIT and closed the stream, which is not an error
+        db.execute_in_transaction(1, "COMMIT".into()).await.unwrap();
+        let err = db
+            .execute_in_transaction(1, "SELECT 1".into())
+            .await
+            .unwrap_err();
+        assert!(err.is_stream_expired(), "{err}");
+        // Nothing is sent on a stream known to be closed
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn read_only_transaction_served_by_replica() {
+        let handler = |request: &Request| {
+            let result = json!({"type": "ok", "response": {"type": "execute", "result": {
+                "cols": [], "rows": [], "affected_row_count": 0, "last_insert_rowid": null,
+            }}});
+            let baton = (request.body["requests"][0]["stmt"]["sql"] != "COMMIT").then_some("b");
+            (200, pipeline_response(baton, json!([result])))
+        };
+        let primary = TestServer::start(handler).await;
+        let replica = TestServer::start(handler).await;
+        let config = Config::new(primary.url())
+            .unwrap()
+            .with_read_replica(replica.url())
+            .unwrap();
+        let inner = InnerClient::Reqwest(crate::reqwest::HttpClient::new());
+        let db = crate::Client::Http(Client::from_config(inner, config).unwrap());
+
+        let tx = db
+            .transaction_with(crate::TransactionBehavior::ReadOnly)
+            .await
+            .unwrap();
+        tx.execute("SELECT 1").await.unwrap();
+        tx.commit().await.unwrap();
+        let sqls: Vec<_> = replica
+            .requests()
+            .iter()
+            .map(|r| r.body["requests"][0]["stmt"]["sql"].clone())
+            .collect();
+        assert_eq!(sqls, ["BEGIN DEFERRED", "SELECT 1", "COMMIT"]);
+        assert!(primary.requests().is_empty());
+
+        // Other transactions are served by the database itself
+        let tx = db.transaction().await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(replica.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn transaction_batch_steps_require_previous_success() {
+        // The first step fails, so the server skips the steps conditioned on it
+        let server = TestServer::start(|_| {
+            let result = json!({"type": "ok", "response": {"type": "batch", "result": {
+                "step_results": [null, null, null],
+                "step_errors": [{"message": "no such table: t"}, null, null],
+            }}});
+            (200, pipeline_response(Some("tx-baton"), json!([result])))
+        })
+        .await;
+        let db = server.client();
+        let stmts = [
+            "INSERT INTO t VALUES (1)",
+            "INSERT INTO t VALUES (2)",
+            "SELECT 3",
+        ];
+        let result = db
+            .batch_in_transaction(1, stmts.into_iter().map(Statement::from).collect())
+            .await
+            .unwrap();
+        assert!(result.step_errors[0].is_some());
+
+        let steps = &server.requests()[0].body["requests"][0]["batch"]["steps"];
+        let conditions: Vec<&serde_json::Value> = steps
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| &step["condition"])
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                &json!(null),
+                &json!({"type": "ok", "step": 0}),
+                &json!({"type": "ok", "step": 1}),
+            ]
+        );
+    }
+
+    #[test]
+    fn stored_sql_closes_least_recently_used() {
+        let mut stored = StoredSql::default();
+        let mut setup = Vec::new();
+        for i in 0..MAX_STORED_SQL {
+            stored.id(&format!("SELECT {i}"), &[], &mut setup);
+        }
+        assert_eq!(stored.id("SELECT 0", &[], &mut setup), Some(0));
+        setup.clear();
+        let next_id = MAX_STORED_SQL as i32;
+        assert_eq!(stored.id("SELECT 'new'", &[], &mut setup), Some(next_id));
+        assert_eq!(
+            serde_json::to_value(&setup).unwrap(),
+            json!([
+                {"type": "close_sql", "sql_id": 1},
+                {"type": "store_sql", "sql_id": next_id, "sql": "SELECT 'new'"},
+            ])
+        );
+        // SQL used by the request being built is never closed
+        let used: Vec<i32> = stored.ids.iter().map(|(_, id)| *id).collect();
+        assert_eq!(stored.id("SELECT 'other'", &used, &mut setup), None);
+    }
+
+    #[tokio::test]
+    async fn cursor_stream_closed_at_end() {
+        let server = TestServer::start(cursor_server()).await;
+        let db = server.client();
+        let rows: Vec<Row> = db
+            .query_stream("SELECT x FROM t")
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        wait_for_close(&server).await;
+    }
+
+    #[tokio::test]
+    async fn dropped_cursor_stream_closed() {
+        let server = TestServer::start(cursor_server()).await;
+        let db = server.client();
+        let mut rows = db.query_stream("SELECT x FROM t").await.unwrap();
+        rows.try_next().await.unwrap().unwrap();
+        drop(rows);
+        wait_for_close(&server).await;
+    }
+
+    #[tokio::test]
+    async fn cursor_falls_back_without_hrana_3() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/v2/pipeline" => (
+                200,
+                pipeline_response(
+                    None,
+                    json!([
+                        {"type": "ok", "response": {"type": "execute", "result": {
+                            "cols": [{"name": "x"}],
+                            "rows": [[{"type": "integer", "value": "1"}]],
+                            "affected_row_count": 0,
+                            "last_insert_rowid": null,
+                        }}},
+                        {"type": "ok", "response": {"type": "close"}},
+                    ]),
+                ),
+            ),
+            _ => (404, "Not Found".into()),
+        })
+        .await;
+        let db = server.client();
+        for _ in 0..2 {
+            let rows: Vec<Row> = db
+                .query_stream("SELECT x FROM t")
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 1);
+        }
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        // The cursor endpoint is only tried once
+        assert_eq!(paths, ["/v3/cursor", "/v2/pipeline", "/v2/pipeline"]);
+    }
 }
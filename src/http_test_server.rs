@@ -0,0 +1,130 @@
+//! Minimal HTTP server used by the tests of the HTTP backends.
+//!
+//! It records every request it receives and answers them with a handler given by the test.
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// A request received by the server, with its path and JSON body
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+    pub(crate) path: String,
+    pub(crate) body: serde_json::Value,
+}
+
+type Handler = dyn Fn(&Request) -> (u16, String) + Send + Sync;
+
+pub(crate) struct TestServer {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    /// Starts a server answering each request with the status and body returned by `handler`.
+    pub(crate) async fn start(
+        handler: impl Fn(&Request) -> (u16, String) + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let (handler, recorded) = (handler.clone(), recorded.clone());
+                tokio::spawn(async move {
+                    let mut socket = BufReader::new(socket);
+                    let mut line = String::new();
+                    socket.read_line(&mut line).await.unwrap();
+                    let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                    let mut content_length = 0;
+                    loop {
+                        line.clear();
+                        socket.read_line(&mut line).await.unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    socket.read_exact(&mut body).await.unwrap();
+                    let request = Request {
+                        path,
+                        body: serde_json::from_slice(&body).unwrap_or_default(),
+                    };
+                    let (status, body) = handler(&request);
+                    recorded.lock().unwrap().push(request);
+                    let response = format!(
+                        "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    socket.write_all(response.as_bytes()).await.ok();
+                });
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Requests received so far, in order
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Starts a server answering pipeline requests like sqld, with a database of the local
+    /// backend. Streams do not outlive their request.
+    #[cfg(feature = "local_backend")]
+    pub(crate) async fn start_sql() -> Self {
+        let db = Mutex::new(crate::local::Client::in_memory().unwrap());
+        Self::start(move |request| {
+            if request.path != "/v2/pipeline" {
+                return (404, "Not Found".into());
+            }
+            let db = db.lock().unwrap();
+            let results: Vec<serde_json::Value> = request.body["requests"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| {
+                    let response = match r["type"].as_str().unwrap() {
+                        "execute" => crate::test_db::execute(&db, &r["stmt"])
+                            .map(|result| json!({"type": "execute", "result": result})),
+                        "batch" => Ok(json!({
+                            "type": "batch",
+                            "result": crate::test_db::batch(&db, &r["batch"]),
+                        })),
+                        other => Ok(json!({ "type": other })),
+                    };
+                    match response {
+                        Ok(response) => json!({"type": "ok", "response": response}),
+                        Err(message) => json!({"type": "error", "error": {"message": message}}),
+                    }
+                })
+                .collect();
+            (200, pipeline_response(None, json!(results)))
+        })
+        .await
+    }
+
+    /// Client of the reqwest backend connected to this server
+    pub(crate) fn client(&self) -> crate::http::Client {
+        let inner = crate::http::InnerClient::Reqwest(crate::reqwest::HttpClient::new());
+        crate::http::Client::new(inner, self.url(), "")
+    }
+}
+
+/// Body of a pipeline response with the given results
+pub(crate) fn pipeline_response(baton: Option<&str>, results: serde_json::Value) -> String {
+    json!({"baton": baton, "base_url": null, "results": results}).to_string()
+}
@@ -6,15 +6,30 @@
 //! libsql-client compiles to wasm32-unknown-unknown target, which makes it a great
 //! driver for environments that run on WebAssembly.
 //!
+pub mod error;
+pub use error::{Error, Result};
+
 pub mod statement;
-pub use statement::Statement;
+pub use statement::{PreparedStatement, Statement};
 
 pub mod proto;
 pub use proto::{BatchResult, Col, Value};
 
-#[cfg(feature = "mapping_names_to_values_in_rows")]
+pub mod batch;
+pub use batch::{BatchBuilder, BatchCond};
+
+pub mod value;
+pub use value::{FromValue, ToValue};
+
 pub mod de;
 
+pub mod ser;
+
+pub mod migrate;
+
+#[cfg(feature = "chrono")]
+pub mod datetime;
+
 #[cfg(feature = "workers_backend")]
 pub use worker;
 
@@ -26,6 +41,25 @@ pub struct Row {
     pub value_map: std::collections::HashMap<String, Value>,
 }
 
+impl Row {
+    // Builds a row from its values, `columns` being the names of the result columns.
+    pub(crate) fn from_values(columns: &[String], values: Vec<Value>) -> Row {
+        #[cfg(feature = "mapping_names_to_values_in_rows")]
+        let value_map = columns
+            .iter()
+            .zip(values.iter())
+            .map(|(c, v)| (c.to_string(), v.clone()))
+            .collect();
+        #[cfg(not(feature = "mapping_names_to_values_in_rows"))]
+        let _ = columns;
+        Row {
+            values,
+            #[cfg(feature = "mapping_names_to_values_in_rows")]
+            value_map,
+        }
+    }
+}
+
 impl<'a> Row {
     /// Try to get a value by index from this row and convert it to the desired type
     ///
@@ -45,15 +79,12 @@ impl<'a> Row {
     /// let text : &str = row.try_get(1).unwrap();
     /// # }
     /// ```
-    pub fn try_get<V: TryFrom<&'a Value, Error = String>>(
-        &'a self,
-        index: usize,
-    ) -> anyhow::Result<V> {
+    pub fn try_get<V: FromValue<'a>>(&'a self, index: usize) -> Result<V> {
         let val = self
             .values
             .get(index)
-            .ok_or(anyhow::anyhow!("out of bound index {}", index))?;
-        val.try_into().map_err(|x: String| anyhow::anyhow!(x))
+            .ok_or_else(|| Error::Conversion(format!("out of bound index {index}")))?;
+        V::from_value(val)
     }
 
     /// Try to get a value given a column name from this row and convert it to the desired type
@@ -75,18 +106,20 @@ impl<'a> Row {
     /// # }
     /// ```
     #[cfg(feature = "mapping_names_to_values_in_rows")]
-    pub fn try_column<V: TryFrom<&'a Value, Error = String>>(
-        &'a self,
-        col: &str,
-    ) -> anyhow::Result<V> {
+    pub fn try_column<V: FromValue<'a>>(&'a self, col: &str) -> Result<V> {
         let val = self
             .value_map
             .get(col)
-            .ok_or(anyhow::anyhow!("column `{}` not present", col))?;
-        val.try_into().map_err(|x: String| anyhow::anyhow!(x))
+            .ok_or_else(|| Error::Conversion(format!("column `{col}` not present")))?;
+        V::from_value(val)
     }
 }
 
+/// Rows of a query, fetched from the database as they are consumed.
+///
+/// Returned by [`Client::query_stream()`]. Dropping the stream stops the query.
+pub type RowStream = futures::stream::BoxStream<'static, Result<Row>>;
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Represents the result of a database query
 ///
@@ -116,43 +149,154 @@ pub struct ResultSet {
     /// the rowid for last insertion. See <https://www.sqlite.org/c3ref/last_insert_rowid.html> for
     /// details
     pub last_insert_rowid: Option<i64>,
+    // Metadata of the columns, in the same order as `columns`, see [ResultSet::column_info]
+    #[serde(default)]
+    pub(crate) column_info: Vec<ColumnInfo>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+/// Metadata of a column of a [ResultSet], see [ResultSet::column_info]
+pub struct ColumnInfo {
+    /// name of the column, as in [ResultSet::columns]
+    pub name: String,
+    /// type of the column as declared in its table, e.g. `INTEGER` or `VARCHAR(20)`.
+    /// `None` for expressions and columns declared without a type.
+    pub decltype: Option<String>,
+    /// table the column comes from, `None` for expressions.
+    /// Only known to the local backend, the remote protocol does not report it.
+    pub table: Option<String>,
+    /// name of the column in its table, which can differ from `name` if it was renamed with `AS`.
+    /// Only known to the local backend, the remote protocol does not report it.
+    pub origin: Option<String>,
+}
+
+impl ResultSet {
+    /// Deserializes every row into `T`, matching struct fields with column names,
+    /// see [`de::from_columns`] for the supported types.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn f() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// db.execute("create table example(num integer, str text)").unwrap();
+    /// db.execute("insert into example (num, str) values (0, 'zero'), (1, 'one')").unwrap();
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Example {
+    ///     num: i64,
+    ///     str: String,
+    /// }
+    /// let rs = db.execute("select * from example").unwrap();
+    /// let examples: Vec<Example> = rs.into_typed().unwrap();
+    /// assert_eq!(examples[1].str, "one");
+    /// # }
+    /// ```
+    pub fn into_typed<T: serde::de::DeserializeOwned>(self) -> Result<Vec<T>> {
+        self.rows
+            .iter()
+            .map(|row| de::from_columns(&self.columns, row))
+            .collect()
+    }
+
+    /// Deserializes the first row into `T`, see [`ResultSet::into_typed`].
+    ///
+    /// Returns [`Error::NoRows`] if there are no rows.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn f() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// db.execute("create table example(num integer, str text)").unwrap();
+    /// db.execute("insert into example (num, str) values (0, 'zero')").unwrap();
+    /// let rs = db.execute("select num, str from example").unwrap();
+    /// let (num, str): (i64, String) = rs.first().unwrap();
+    /// # }
+    /// ```
+    pub fn first<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        let row = self.rows.first().ok_or(Error::NoRows)?;
+        de::from_columns(&self.columns, row)
+    }
+
+    /// Converts the first column of the first row to the desired type, see [`Row::try_get`].
+    ///
+    /// Returns [`Error::NoRows`] if there are no rows.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn f() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// db.execute("create table example(num integer, str text)").unwrap();
+    /// let rs = db.execute("select count(*) from example").unwrap();
+    /// let count: i64 = rs.scalar().unwrap();
+    /// assert_eq!(count, 0);
+    /// # }
+    /// ```
+    pub fn scalar<'a, V: FromValue<'a>>(&'a self) -> Result<V> {
+        self.rows.first().ok_or(Error::NoRows)?.try_get(0)
+    }
+
+    /// Metadata of the columns present in this `ResultSet`, in the same order as [ResultSet::columns]
+    ///
+    /// The hrana (WebSocket) backend only reports column names,
+    /// since the underlying client drops the declared types.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn f() {
+    /// let db = libsql_client::SyncClient::in_memory().unwrap();
+    /// db.execute("create table example(num INTEGER, str TEXT)").unwrap();
+    /// let rs = db.execute("select num, str as label, 42 from example").unwrap();
+    /// let info = rs.column_info();
+    /// assert_eq!(info[1].name, "label");
+    /// assert_eq!(info[1].decltype.as_deref(), Some("TEXT"));
+    /// assert_eq!(info[2].decltype, None);
+    /// # }
+    /// ```
+    pub fn column_info(&self) -> &[ColumnInfo] {
+        &self.column_info
+    }
+
+    // Deserializes the only row, if any, failing if there are more.
+    pub(crate) fn optional<T: serde::de::DeserializeOwned>(&self) -> Result<Option<T>> {
+        match self.rows.len() {
+            0 => Ok(None),
+            1 => self.first().map(Some),
+            n => Err(Error::TooManyRows(n)),
+        }
+    }
 }
 
 impl std::convert::From<proto::StmtResult> for ResultSet {
     fn from(value: proto::StmtResult) -> Self {
-        let columns: Vec<String> = value
+        let column_info: Vec<ColumnInfo> = value
             .cols
             .into_iter()
-            .map(|c| c.name.unwrap_or_default())
+            .map(|c| ColumnInfo {
+                name: c.name.unwrap_or_default(),
+                decltype: c.decltype,
+                table: c.table,
+                origin: c.origin,
+            })
             .collect();
+        let columns: Vec<String> = column_info.iter().map(|c| c.name.clone()).collect();
         let rows = value
             .rows
             .into_iter()
-            .map(|values| {
-                #[cfg(feature = "mapping_names_to_values_in_rows")]
-                let value_map = columns
-                    .iter()
-                    .enumerate()
-                    .map(|(i, c)| (c.to_string(), values[i].clone()))
-                    .collect();
-                Row {
-                    values,
-                    #[cfg(feature = "mapping_names_to_values_in_rows")]
-                    value_map,
-                }
-            })
+            .map(|values| Row::from_values(&columns, values))
             .collect();
         ResultSet {
             columns,
             rows,
             rows_affected: value.affected_row_count,
             last_insert_rowid: value.last_insert_rowid,
+            column_info,
         }
     }
 }
 
 pub mod client;
-pub use client::{Client, Config, SyncClient};
+pub use client::{Client, Config, PoolConfig, RetryPolicy, SyncClient, Timeouts};
 
 #[cfg(any(
     feature = "reqwest_backend",
@@ -161,7 +305,10 @@ pub use client::{Client, Config, SyncClient};
 ))]
 pub mod http;
 pub mod transaction;
-pub use transaction::{SyncTransaction, Transaction};
+pub use transaction::{
+    OwnedTransaction, Savepoint, SyncOwnedTransaction, SyncSavepoint, SyncTransaction, SyncTx,
+    Transaction, TransactionBehavior, Tx,
+};
 
 #[cfg(feature = "workers_backend")]
 pub mod workers;
@@ -177,6 +324,14 @@ pub mod spin;
 
 #[cfg(feature = "hrana_backend")]
 pub mod hrana;
+#[cfg(feature = "hrana_backend")]
+mod hrana_pool;
+#[cfg(all(test, feature = "hrana_backend", feature = "local_backend"))]
+mod hrana_test_server;
+#[cfg(all(test, feature = "reqwest_backend"))]
+mod http_test_server;
+#[cfg(all(test, feature = "local_backend"))]
+mod test_db;
 mod utils;
 
 /// A macro for passing parameters to statements without having to manually
@@ -199,6 +354,6 @@ mod utils;
 macro_rules! args {
     () => { &[] };
     ($($param:expr),+ $(,)?) => {
-        &[$($param.into()),+] as &[libsql_client::Value]
+        &[$($crate::ToValue::to_value(&$param)),+] as &[$crate::Value]
     };
 }
@@ -1,15 +1,114 @@
-use crate::{proto, proto::StmtResult, BatchResult, Col, ResultSet, Statement, Value};
-use anyhow::Result;
-use sqlite3_parser::ast::{Cmd, Stmt};
-use sqlite3_parser::lexer::sql::Parser;
-
-use fallible_iterator::FallibleIterator;
+use crate::batch::Emulation;
+use crate::{
+    proto::StmtResult, BatchBuilder, BatchResult, Col, Error, PreparedStatement, Result, ResultSet,
+    Row, RowStream, Statement, Value,
+};
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
 
 /// Database client. This is the main structure used to
 /// communicate with the database.
 pub struct Client {
-    db: libsql::Database,
-    conn: libsql::Connection,
+    db: Database,
+    /// The connection is locked while a statement or batch runs, so that the changes
+    /// and the rowid it reports are its own when the client is shared between threads
+    conn: Mutex<libsql::Connection>,
+    /// Compiled statements created by [Client::prepare]
+    prepared: Mutex<PreparedCache>,
+    /// Transaction which the connection is running, by id
+    transaction: Mutex<Option<u64>>,
+    /// Notified when the transaction running on the connection ends
+    transaction_ended: Condvar,
+}
+
+/// How long beginning a transaction waits for the one running on the connection to end,
+/// before failing with `SQLITE_BUSY` like SQLite does when the database is locked.
+const TRANSACTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Most compiled statements kept by a client. Preparing or executing another one
+/// drops the least recently used statement.
+const PREPARED_CACHE_CAPACITY: usize = 128;
+
+/// Compiled statements by SQL text, with the tick of their last use.
+#[derive(Default)]
+struct PreparedCache {
+    entries: HashMap<String, (Compiled, u64)>,
+    tick: u64,
+}
+
+impl PreparedCache {
+    fn take(&mut self, sql: &str) -> Option<Compiled> {
+        self.entries.remove(sql).map(|(compiled, _)| compiled)
+    }
+
+    fn insert(&mut self, sql: String, compiled: Compiled) {
+        self.tick += 1;
+        if self.entries.len() >= PREPARED_CACHE_CAPACITY && !self.entries.contains_key(&sql) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(sql, _)| sql.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(sql, (compiled, self.tick));
+    }
+}
+
+/// A compiled statement, cached by [Client::prepare].
+struct Compiled {
+    stmt: libsql::Statement,
+}
+
+impl Compiled {
+    // Rows affected and the last inserted rowid are only reported for statements which
+    // actually changed the database, like sqld does. Changes are detected through the
+    // total number of changes made on the connection, which also covers statements that
+    // only write through triggers, UPSERTs and RETURNING clauses.
+    fn execute(&self, conn: &libsql::Connection, stmt: Statement) -> Result<StmtResult> {
+        let params = params(&self.stmt, stmt.args, stmt.named_args)?;
+        let cols: Vec<Col> = self
+            .stmt
+            .columns()
+            .into_iter()
+            .map(|c| Col {
+                name: Some(c.name().to_string()),
+                decltype: c.decl_type().map(str::to_string),
+                table: c.table_name().map(str::to_string),
+                origin: c.origin_name().map(str::to_string),
+            })
+            .collect();
+        let readonly = self.stmt.readonly();
+        let changes_before = total_changes(conn);
+        let mut rows = Vec::new();
+        let input_rows = self.stmt.query(&params)?;
+        while let Some(row) = input_rows.next()? {
+            rows.push(row_values(&row, cols.len()))
+        }
+        let changed = !readonly && total_changes(conn) != changes_before;
+        Ok(StmtResult {
+            cols,
+            rows,
+            affected_row_count: if changed { conn.changes() } else { 0 },
+            last_insert_rowid: changed.then(|| conn.last_insert_rowid()),
+        })
+    }
+}
+
+// The database, which is always opened without a replicator.
+struct Database(libsql::Database);
+
+// Only the replicator of a database is not shared between threads safely, and the
+// database is only used to sync it, which fails without touching any replicator.
+unsafe impl Sync for Database {}
+
+// Number of rows changed on the connection since it was opened, including by triggers.
+fn total_changes(conn: &libsql::Connection) -> i64 {
+    // SAFETY: the handle comes from a connection which is open while borrowed,
+    // and sqlite3_total_changes64 only reads a counter of that connection.
+    unsafe { libsql::ffi::sqlite3_total_changes64(conn.handle()) }
 }
 
 impl std::fmt::Debug for Client {
@@ -44,43 +143,112 @@ impl From<libsql::Value> for ValueWrapper {
     }
 }
 
+// Error of arguments which cannot be bound to the parameters of a statement,
+// reported like SQLite reports a parameter index out of range.
+fn bind_error(message: String) -> Error {
+    Error::Sqlite {
+        code: Some(crate::error::codes::SQLITE_RANGE),
+        message,
+    }
+}
+
+// Converts bound arguments of a statement to libsql parameters. A name without a prefix
+// matches a `:name`, `@name` or `$name` placeholder of the statement.
+fn params(
+    stmt: &libsql::Statement,
+    args: Vec<Value>,
+    named_args: Vec<(String, Value)>,
+) -> Result<libsql::Params> {
+    if named_args.is_empty() {
+        return Ok(args
+            .into_iter()
+            .map(ValueWrapper)
+            .map(libsql::Value::from)
+            .collect::<Vec<_>>()
+            .into());
+    }
+    if !args.is_empty() {
+        return Err(bind_error(
+            "Statements cannot bind both positional and named arguments".into(),
+        ));
+    }
+    let placeholders: Vec<&str> = (1..=stmt.parameter_count() as i32)
+        .filter_map(|i| stmt.parameter_name(i))
+        .collect();
+    let named_args = named_args
+        .into_iter()
+        .map(|(name, value)| {
+            let placeholder = placeholders
+                .iter()
+                .find(|p| **p == name || (!name.starts_with([':', '@', '$']) && p[1..] == name))
+                .ok_or_else(|| bind_error(format!("Statement has no parameter named {name}")))?;
+            Ok((placeholder.to_string(), ValueWrapper(value).into()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(libsql::Params::Named(named_args))
+}
+
+// Reads the values of the first `count` columns of a row.
+fn row_values(row: &libsql::Row, count: usize) -> Vec<Value> {
+    (0..count)
+        .map(|i| ValueWrapper::from(row.get_value(i as i32).unwrap()).0)
+        .collect()
+}
+
 impl Client {
     /// Establishes a database client.
     ///
     /// # Arguments
     /// * `path` - path of the local database
-    pub fn new(path: impl Into<String>) -> anyhow::Result<Self> {
+    pub fn new(path: impl Into<String>) -> Result<Self> {
         let db = libsql::Database::open(path.into())?;
         let conn = db.connect()?;
-        Ok(Self { db, conn })
+        Ok(Self::with_connection(db, conn))
     }
 
     /// Establishes a new in-memory database and connects to it.
-    pub fn in_memory() -> anyhow::Result<Self> {
+    pub fn in_memory() -> Result<Self> {
         let db = libsql::Database::open(":memory:")?;
         let conn = db.connect()?;
-        Ok(Self { db, conn })
+        Ok(Self::with_connection(db, conn))
     }
 
-    pub fn from_env() -> anyhow::Result<Self> {
+    fn with_connection(db: libsql::Database, conn: libsql::Connection) -> Self {
+        Self {
+            db: Database(db),
+            conn: Mutex::new(conn),
+            prepared: Mutex::default(),
+            transaction: Mutex::default(),
+            transaction_ended: Condvar::new(),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
         let path = std::env::var("LIBSQL_CLIENT_URL").map_err(|_| {
-            anyhow::anyhow!("LIBSQL_CLIENT_URL variable should point to your sqld database")
+            Error::Config("LIBSQL_CLIENT_URL variable should point to your sqld database".into())
         })?;
         let path = match path.strip_prefix("file:///") {
             Some(path) => path,
-            None => anyhow::bail!("Local URL needs to start with file:///"),
+            None => {
+                return Err(Error::Config(
+                    "Local URL needs to start with file:///".into(),
+                ))
+            }
         };
         Self::new(path)
     }
 
-    pub async fn sync(&self) -> anyhow::Result<usize> {
-        self.db.sync().await.map_err(|e| anyhow::anyhow!("{}", e))
+    pub async fn sync(&self) -> Result<usize> {
+        self.db.0.sync().await.map_err(Error::from)
     }
 
     /// Executes a batch of SQL statements.
     /// Each statement is going to run in its own transaction,
     /// unless they're wrapped in BEGIN and END
     ///
+    /// The batch stops at the first failed statement, unlike [Client::execute_batch],
+    /// which keeps running the steps whose conditions hold.
+    ///
     /// # Arguments
     /// * `stmts` - SQL statements
     ///
@@ -96,74 +264,86 @@ impl Client {
     pub fn raw_batch(
         &self,
         stmts: impl IntoIterator<Item = impl Into<Statement>>,
-    ) -> anyhow::Result<BatchResult> {
+    ) -> Result<BatchResult> {
+        Ok(self.run_steps(&self.conn(), stmts))
+    }
+
+    // Runs statements until the first failed one, on a connection locked for the whole batch.
+    fn run_steps(
+        &self,
+        conn: &libsql::Connection,
+        stmts: impl IntoIterator<Item = impl Into<Statement>>,
+    ) -> BatchResult {
         let mut step_results = vec![];
         let mut step_errors = vec![];
         for stmt in stmts {
-            let stmt = stmt.into();
-            let sql_string = &stmt.sql;
-            let params: libsql::Params = stmt
-                .args
-                .into_iter()
-                .map(ValueWrapper)
-                .map(libsql::Value::from)
-                .collect::<Vec<_>>()
-                .into();
-            let stmt = self.conn.prepare(sql_string)?;
-            let cols: Vec<Col> = stmt
-                .columns()
-                .into_iter()
-                .map(|c| Col {
-                    name: Some(c.name().to_string()),
-                })
-                .collect();
-            let mut rows = Vec::new();
-            let input_rows = match stmt.query(&params) {
-                Ok(rows) => rows,
+            match self.execute_step(conn, stmt.into()) {
+                Ok(stmt_result) => {
+                    step_results.push(Some(stmt_result));
+                    step_errors.push(None);
+                }
                 Err(e) => {
                     step_results.push(None);
-                    step_errors.push(Some(proto::Error {
-                        message: e.to_string(),
-                    }));
+                    step_errors.push(Some(crate::batch::step_error(e)));
                     break;
                 }
-            };
-            while let Some(row) = input_rows.next()? {
-                let cells = (0..cols.len())
-                    .map(|i| ValueWrapper::from(row.get_value(i as i32).unwrap()).0)
-                    .collect();
-                rows.push(cells)
             }
-            let parser = Parser::new(sql_string.as_bytes());
-            let cmd = parser.last();
-
-            let last_insert_rowid = match cmd {
-                Ok(Some(Cmd::Stmt(Stmt::Insert { .. }))) => Some(self.conn.last_insert_rowid()),
-                _ => None,
-            };
-
-            let affected_row_count = match cmd {
-                Ok(Some(
-                    Cmd::Stmt(Stmt::Insert { .. })
-                    | Cmd::Stmt(Stmt::Update { .. })
-                    | Cmd::Stmt(Stmt::Delete { .. }),
-                )) => self.conn.changes(),
-                _ => 0,
-            };
-
-            let stmt_result = StmtResult {
-                cols,
-                rows,
-                affected_row_count,
-                last_insert_rowid,
-            };
-            step_results.push(Some(stmt_result));
-            step_errors.push(None);
-        }
-        Ok(BatchResult {
+        }
+        BatchResult {
             step_results,
             step_errors,
-        })
+        }
+    }
+
+    /// Executes a batch whose steps may be conditional, see [BatchBuilder].
+    /// Conditions are evaluated after each step.
+    pub fn execute_batch(&self, batch: BatchBuilder) -> Result<BatchResult> {
+        let conn = self.conn();
+        let mut emulation = Emulation::default();
+        for (cond, stmt) in batch.into_steps() {
+            let outcome = emulation
+                .should_run(cond.as_ref())
+                .then(|| self.execute_step(&conn, stmt));
+            emulation.record(outcome);
+        }
+        Ok(emulation.finish())
+    }
+
+    /// Compiles a statement once, so that executing it later with different
+    /// arguments skips parsing the SQL, see [PreparedStatement].
+    pub fn prepare(&self, sql: impl Into<String>) -> Result<PreparedStatement> {
+        let sql = sql.into();
+        let compiled = self.compile(&self.conn(), &sql)?;
+        self.prepared.lock().unwrap().insert(sql.clone(), compiled);
+        Ok(PreparedStatement::new(sql))
+    }
+
+    // Executes a single statement, preserving the extended error code on failure.
+    fn execute_step(&self, conn: &libsql::Connection, stmt: Statement) -> Result<StmtResult> {
+        if !stmt.prepared {
+            return self.compile(conn, &stmt.sql)?.execute(conn, stmt);
+        }
+        // The compiled statement is taken out of the cache while in use, so that
+        // concurrent executions of the same SQL compile their own copy instead.
+        let cached = self.prepared.lock().unwrap().take(&stmt.sql);
+        let compiled = match cached {
+            Some(compiled) => compiled,
+            None => self.compile(conn, &stmt.sql)?,
+        };
+        let sql = stmt.sql.clone();
+        let result = compiled.execute(conn, stmt);
+        compiled.stmt.reset();
+        self.prepared.lock().unwrap().insert(sql, compiled);
+        result
+    }
+
+    fn compile(&self, conn: &libsql::Connection, sql: &str) -> Result<Compiled> {
+        let stmt = conn.prepare(sql)?;
+        Ok(Compiled { stmt })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, libsql::Connection> {
+        self.conn.lock().unwrap()
     }
 
     /// Executes a batch of SQL statements, wrapped in "BEGIN", "END", transaction-style.
@@ -176,55 +356,251 @@ impl Client {
         &self,
         stmts: impl IntoIterator<Item = impl Into<Statement> + Send> + Send,
     ) -> Result<Vec<ResultSet>> {
-        let batch_results = self.raw_batch(
+        let stmts: Vec<Statement> = stmts.into_iter().map(|s| s.into()).collect();
+        let sqls = stmts.iter().map(|s| s.sql.clone()).collect();
+        let conn = self.conn();
+        let batch_results = self.run_steps(
+            &conn,
             std::iter::once(Statement::new("BEGIN"))
-                .chain(stmts.into_iter().map(|s| s.into()))
+                .chain(stmts)
                 .chain(std::iter::once(Statement::new("END"))),
-        )?;
-        let step_error: Option<proto::Error> = batch_results
-            .step_errors
-            .into_iter()
-            .skip(1)
-            .find(|e| e.is_some())
-            .flatten();
-        if let Some(error) = step_error {
-            return Err(anyhow::anyhow!(error.message));
-        }
-        let mut step_results: Vec<Result<ResultSet>> = batch_results
-            .step_results
-            .into_iter()
-            .skip(1) // BEGIN is not counted in the result, it's implicitly ignored
-            .map(|maybe_rs| {
-                maybe_rs
-                    .map(ResultSet::from)
-                    .ok_or_else(|| anyhow::anyhow!("Unexpected missing result set"))
-            })
-            .collect();
-        step_results.pop(); // END is not counted in the result, it's implicitly ignored
-                            // Collect all the results into a single Result
-        step_results.into_iter().collect::<Result<Vec<ResultSet>>>()
+        );
+        let failed = batch_results.step_errors.iter().any(|e| e.is_some());
+        if failed && !conn.is_autocommit() {
+            // The batch stops at the first error, so END was never executed
+            self.execute_step(&conn, Statement::new("ROLLBACK")).ok();
+        }
+        drop(conn);
+        crate::client::batch_result_sets(batch_results, sqls)
     }
 
     /// # Arguments
     /// * `stmt` - the SQL statement
     pub fn execute(&self, stmt: impl Into<Statement> + Send) -> Result<ResultSet> {
-        let results = self.raw_batch(std::iter::once(stmt))?;
-        match (results.step_results.first(), results.step_errors.first()) {
-            (Some(Some(result)), Some(None)) => Ok(ResultSet::from(result.clone())),
-            (Some(None), Some(Some(err))) => Err(anyhow::anyhow!(err.message.clone())),
-            _ => unreachable!(),
+        self.execute_step(&self.conn(), stmt.into())
+            .map(ResultSet::from)
+    }
+
+    /// Executes a statement, stepping through its rows only as they are consumed
+    /// from the returned stream.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn f() {
+    /// use futures::TryStreamExt;
+    ///
+    /// let db = libsql_client::local::Client::in_memory().unwrap();
+    /// let mut rows = db.query_stream("SELECT 1 UNION ALL SELECT 2").unwrap();
+    /// while let Some(row) = rows.try_next().await.unwrap() {
+    ///     println!("{:?}", row.values);
+    /// }
+    /// # }
+    /// ```
+    pub fn query_stream(&self, stmt: impl Into<Statement>) -> Result<RowStream> {
+        let stmt = stmt.into();
+        let prepared = self.conn().prepare(&stmt.sql)?;
+        let columns: Vec<String> = prepared
+            .columns()
+            .into_iter()
+            .map(|c| c.name().to_string())
+            .collect();
+        let rows = prepared.query(&params(&prepared, stmt.args, stmt.named_args)?)?;
+        let mut done = false;
+        let rows = std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            match rows.next() {
+                Ok(Some(row)) => Some(Ok(Row::from_values(
+                    &columns,
+                    row_values(&row, columns.len()),
+                ))),
+                Ok(None) => {
+                    done = true;
+                    None
+                }
+                Err(e) => {
+                    done = true;
+                    Some(Err(e.into()))
+                }
+            }
+        });
+        Ok(Box::pin(futures::stream::iter(rows)))
+    }
+
+    /// Executes a statement of a transaction. The first statement of a transaction waits
+    /// for the one running on the connection to end, since both would share it.
+    pub fn execute_in_transaction(&self, tx_id: u64, stmt: Statement) -> Result<ResultSet> {
+        self.in_transaction(tx_id, |conn| self.execute_step(conn, stmt))
+            .map(ResultSet::from)
+    }
+
+    pub fn batch_in_transaction(&self, tx_id: u64, stmts: Vec<Statement>) -> Result<BatchResult> {
+        self.in_transaction(tx_id, |conn| Ok(self.run_steps(conn, stmts)))
+    }
+
+    pub fn commit_transaction(&self, tx_id: u64) -> Result<()> {
+        self.in_transaction(tx_id, |conn| self.execute_step(conn, "COMMIT".into()))
+            .map(|_| ())
+    }
+
+    pub fn rollback_transaction(&self, tx_id: u64) -> Result<()> {
+        self.in_transaction(tx_id, |conn| self.execute_step(conn, "ROLLBACK".into()))
+            .map(|_| ())
+    }
+
+    /// Rolls back a transaction dropped without being committed or rolled back,
+    /// unless it was already ended by a failed statement.
+    pub fn drop_transaction(&self, tx_id: u64) {
+        if *self.transaction.lock().unwrap() != Some(tx_id) {
+            return;
+        }
+        self.in_transaction(tx_id, |conn| match conn.is_autocommit() {
+            true => Ok(()),
+            false => self.execute_step(conn, "ROLLBACK".into()).map(|_| ()),
+        })
+        .ok();
+    }
+
+    // Runs `f` on the connection for the transaction `tx_id`, which owns the connection
+    // from its first statement until the connection is back in autocommit mode.
+    fn in_transaction<T>(
+        &self,
+        tx_id: u64,
+        f: impl FnOnce(&libsql::Connection) -> Result<T>,
+    ) -> Result<T> {
+        let owner = self.transaction.lock().unwrap();
+        let (mut owner, _) = self
+            .transaction_ended
+            .wait_timeout_while(owner, TRANSACTION_TIMEOUT, |owner| {
+                owner.is_some_and(|owner| owner != tx_id)
+            })
+            .unwrap();
+        if owner.is_some_and(|owner| owner != tx_id) {
+            return Err(Error::Sqlite {
+                code: Some(crate::error::codes::SQLITE_BUSY),
+                message: "Another transaction is running on the connection".into(),
+            });
+        }
+        let conn = self.conn();
+        let result = f(&conn);
+        if conn.is_autocommit() {
+            *owner = None;
+            self.transaction_ended.notify_all();
+        } else {
+            *owner = Some(tx_id);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::codes;
+
+    fn named_param(sql: &str, name: &str) -> Result<Value> {
+        let db = Client::in_memory()?;
+        let rs = db.execute(Statement::with_named_args(sql, [(name, 42)]))?;
+        Ok(rs.rows[0].values[0].clone())
+    }
+
+    #[test]
+    fn named_params_with_prefix() {
+        for sql in ["SELECT :x", "SELECT @x", "SELECT $x"] {
+            let name = &sql[7..];
+            let value = named_param(sql, name).unwrap();
+            assert!(matches!(value, Value::Integer { value: 42 }), "{sql}");
+        }
+    }
+
+    #[test]
+    fn bare_name_matches_any_prefix() {
+        for sql in ["SELECT :x", "SELECT @x", "SELECT $x"] {
+            let value = named_param(sql, "x").unwrap();
+            assert!(matches!(value, Value::Integer { value: 42 }), "{sql}");
         }
     }
 
-    pub fn execute_in_transaction(&self, _tx_id: u64, stmt: Statement) -> Result<ResultSet> {
-        self.execute(stmt)
+    #[test]
+    fn unknown_named_param_rejected() {
+        for (sql, name) in [("SELECT $x", ":x"), ("SELECT :x", "y")] {
+            let err = named_param(sql, name).unwrap_err();
+            assert_eq!(err.sqlite_code(), Some(codes::SQLITE_RANGE), "{sql}");
+        }
     }
 
-    pub fn commit_transaction(&self, _tx_id: u64) -> Result<()> {
-        self.execute("COMMIT").map(|_| ())
+    #[test]
+    fn mixed_params_rejected() {
+        let db = Client::in_memory().unwrap();
+        let mut stmt = Statement::with_named_args("SELECT ?, :x", [("x", 1)]);
+        stmt.args.push(Value::Integer { value: 2 });
+        let err = db.execute(stmt).unwrap_err();
+        assert_eq!(err.sqlite_code(), Some(codes::SQLITE_RANGE));
     }
 
-    pub fn rollback_transaction(&self, _tx_id: u64) -> Result<()> {
-        self.execute("ROLLBACK").map(|_| ())
+    #[test]
+    fn prepared_statement_reused() {
+        let db = Client::in_memory().unwrap();
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let insert = db.prepare("INSERT INTO t VALUES (?)").unwrap();
+        db.execute(insert.bind(&[1])).unwrap();
+        db.execute(insert.bind(&[2])).unwrap();
+
+        let rs = db.execute("SELECT x FROM t ORDER BY x").unwrap();
+        assert_eq!(rs.rows.len(), 2);
+        let cache = db.prepared.lock().unwrap();
+        assert_eq!(cache.entries.len(), 1);
+        // Both executions ran the statement compiled by `prepare`
+        let (compiled, _) = &cache.entries[insert.sql()];
+        let runs = compiled
+            .stmt
+            .get_status(libsql::ffi::SQLITE_STMTSTATUS_RUN as i32);
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn prepared_cache_drops_least_recently_used() {
+        let db = Client::in_memory().unwrap();
+        let first = db.prepare("SELECT 0").unwrap();
+        for i in 1..PREPARED_CACHE_CAPACITY {
+            db.prepare(format!("SELECT {i}")).unwrap();
+        }
+        db.execute(&first).unwrap();
+        db.prepare("SELECT 'one too many'").unwrap();
+
+        let cache = db.prepared.lock().unwrap();
+        assert_eq!(cache.entries.len(), PREPARED_CACHE_CAPACITY);
+        assert!(cache.entries.contains_key("SELECT 0"));
+        assert!(!cache.entries.contains_key("SELECT 1"));
+    }
+
+    #[test]
+    fn concurrent_statements_report_their_own_changes() {
+        let db = Client::in_memory().unwrap();
+        db.execute("CREATE TABLE t(id INTEGER PRIMARY KEY, thread)")
+            .unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let db = &db;
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        let stmt =
+                            Statement::with_args("INSERT INTO t(thread) VALUES (?)", &[thread]);
+                        let rs = db.execute(stmt).unwrap();
+                        assert_eq!(rs.rows_affected, 1);
+                        let id = rs.last_insert_rowid.unwrap();
+                        let rs = db
+                            .execute(Statement::with_args(
+                                "SELECT thread FROM t WHERE id = ?",
+                                &[id],
+                            ))
+                            .unwrap();
+                        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(thread,)]);
+                    }
+                });
+            }
+        });
     }
 }
@@ -0,0 +1,425 @@
+//! `migrate` applies versioned SQL migrations to a database.
+//!
+//! Applied migrations are recorded, along with a checksum of their SQL, in the
+//! `_libsql_migrations` table of the database. Each pending migration is applied in its own
+//! transaction, together with its record, through [`Client::batch`], so it works the same
+//! with every backend. A migration that was edited after being applied is reported as an
+//! error instead of being silently skipped.
+//!
+//! Since each migration already runs in a transaction, its SQL cannot begin or end
+//! transactions itself: migrations containing `BEGIN`, `COMMIT`, `END` or `ROLLBACK`
+//! are rejected. Savepoints can be used instead.
+//!
+//! # Examples
+//!
+//! ```
+//! # async fn f() -> libsql_client::Result<()> {
+//! use libsql_client::migrate::{Migration, Migrator};
+//!
+//! let db = libsql_client::Client::in_memory()?;
+//! let migrator = Migrator::new([
+//!     Migration::new(1, "create_users", "CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT)"),
+//!     Migration::new(2, "add_email", "ALTER TABLE users ADD COLUMN email TEXT"),
+//! ])?;
+//! println!("{}", migrator.dry_run(&db).await?);
+//! let applied = migrator.run(&db).await?;
+//! assert_eq!(applied.len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use crate::{utils, Client, Error, Result, Statement, ToValue};
+
+/// Name of the table recording the applied migrations
+pub const MIGRATIONS_TABLE: &str = "_libsql_migrations";
+
+/// A versioned SQL migration, made of one or more statements
+#[derive(Clone, Debug)]
+pub struct Migration {
+    version: i64,
+    name: String,
+    sql: String,
+}
+
+impl Migration {
+    /// Creates a migration, usually with SQL embedded with `include_str!`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let migration = libsql_client::migrate::Migration::new(
+    ///     1,
+    ///     "create_users",
+    ///     "CREATE TABLE users(id INTEGER PRIMARY KEY, name TEXT);",
+    /// );
+    /// ```
+    pub fn new(version: i64, name: impl Into<String>, sql: impl Into<String>) -> Self {
+        Self {
+            version,
+            name: name.into(),
+            sql: sql.into(),
+        }
+    }
+
+    /// Version of the migration, migrations are applied in increasing order of versions
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// Name of the migration
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// SQL text of the migration
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Checksum of the SQL text, recorded when the migration is applied
+    /// to detect later edits. It is the 64-bit FNV-1a hash of the text, in hexadecimal.
+    pub fn checksum(&self) -> String {
+        let hash = self.sql.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{hash:016x}")
+    }
+
+    // Statements of the SQL text, which must not begin or end transactions.
+    fn sql_statements(&self) -> Result<Vec<String>> {
+        let stmts = utils::split_statements(&self.sql)?;
+        if let Some(stmt) = stmts.iter().find(|s| utils::is_transaction_control(s)) {
+            return Err(Error::Migration(format!(
+                "migration {} ({}) cannot control transactions, but contains `{stmt}`",
+                self.version, self.name
+            )));
+        }
+        Ok(stmts)
+    }
+
+    // Statements applying the migration and recording it.
+    fn statements(&self) -> Result<Vec<Statement>> {
+        let mut stmts: Vec<Statement> = self
+            .sql_statements()?
+            .into_iter()
+            .map(Statement::new)
+            .collect();
+        stmts.push(Statement::with_args(
+            format!("INSERT INTO {MIGRATIONS_TABLE}(version, name, checksum) VALUES (?, ?, ?)"),
+            &[
+                self.version.to_value(),
+                self.name.to_value(),
+                self.checksum().to_value(),
+            ],
+        ));
+        Ok(stmts)
+    }
+}
+
+/// Applies a set of migrations, see the [module documentation](self)
+#[derive(Clone, Debug)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    /// Creates a migrator for the given migrations, which must have distinct versions
+    pub fn new(migrations: impl IntoIterator<Item = Migration>) -> Result<Self> {
+        let mut migrations: Vec<Migration> = migrations.into_iter().collect();
+        migrations.sort_by_key(|m| m.version);
+        if let Some(w) = migrations.windows(2).find(|w| w[0].version == w[1].version) {
+            return Err(Error::Migration(format!(
+                "migrations {} and {} have the same version {}",
+                w[0].name, w[1].name, w[0].version
+            )));
+        }
+        Ok(Self { migrations })
+    }
+
+    /// Creates a migrator for the `.sql` files of a directory, named `<version>_<name>.sql`,
+    /// e.g. `0001_create_users.sql`. Other files are ignored.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let read_error =
+            |e: std::io::Error| Error::Migration(format!("cannot read {}: {e}", dir.display()));
+        let mut migrations = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("sql") {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            let (version, name) = stem
+                .split_once('_')
+                .and_then(|(version, name)| Some((version.parse().ok()?, name)))
+                .ok_or_else(|| {
+                    Error::Migration(format!(
+                        "{} is not named <version>_<name>.sql",
+                        path.display()
+                    ))
+                })?;
+            let sql = std::fs::read_to_string(&path).map_err(read_error)?;
+            migrations.push(Migration::new(version, name, sql));
+        }
+        Self::new(migrations)
+    }
+
+    /// All the migrations, in the order they are applied
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    /// Returns the migrations not applied to the database yet, without writing to it.
+    ///
+    /// Fails if an applied migration was edited since, or is not known to this migrator.
+    pub async fn pending(&self, db: &Client) -> Result<Vec<&Migration>> {
+        let applied = Self::applied(db).await?;
+
+        for (version, name, checksum) in &applied {
+            match self.migrations.iter().find(|m| m.version == *version) {
+                Some(m) if m.checksum() == *checksum => {}
+                Some(m) => {
+                    return Err(Error::Migration(format!(
+                        "migration {version} ({}) was edited after being applied",
+                        m.name
+                    )))
+                }
+                None => {
+                    return Err(Error::Migration(format!(
+                        "applied migration {version} ({name}) is unknown"
+                    )))
+                }
+            }
+        }
+        Ok(self
+            .migrations
+            .iter()
+            .filter(|m| !applied.iter().any(|(version, ..)| *version == m.version))
+            .collect())
+    }
+
+    // Versions, names and checksums of the applied migrations, none if the table
+    // recording them does not exist yet.
+    async fn applied(db: &Client) -> Result<Vec<(i64, String, String)>> {
+        let table = db
+            .execute(Statement::with_args(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+                &[MIGRATIONS_TABLE],
+            ))
+            .await?;
+        if table.rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        db.execute(format!(
+            "SELECT version, name, checksum FROM {MIGRATIONS_TABLE} ORDER BY version"
+        ))
+        .await?
+        .into_typed()
+    }
+
+    /// Applies the pending migrations in order, each one in its own transaction,
+    /// and returns them.
+    ///
+    /// If a migration fails, the previous ones stay applied.
+    pub async fn run(&self, db: &Client) -> Result<Vec<&Migration>> {
+        db.execute(format!(
+            "CREATE TABLE IF NOT EXISTS {MIGRATIONS_TABLE} (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"
+        ))
+        .await?;
+        let pending = self.pending(db).await?;
+        // Invalid migrations are reported before any of them is applied
+        for migration in &pending {
+            migration.sql_statements()?;
+        }
+        for migration in &pending {
+            tracing::debug!(
+                "Applying migration {} ({})",
+                migration.version,
+                migration.name
+            );
+            db.batch(migration.statements()?).await?;
+        }
+        Ok(pending)
+    }
+
+    /// Returns the SQL script that [`Migrator::run`] would execute, without applying it
+    /// or writing anything else to the database.
+    pub async fn dry_run(&self, db: &Client) -> Result<String> {
+        let mut script = String::new();
+        for migration in self.pending(db).await? {
+            script.push_str(&format!(
+                "-- {} ({})\nBEGIN;\n",
+                migration.version, migration.name
+            ));
+            for stmt in migration.sql_statements()? {
+                script.push_str(&stmt);
+                script.push_str(";\n");
+            }
+            script.push_str(&format!(
+                "INSERT INTO {MIGRATIONS_TABLE}(version, name, checksum) VALUES ({}, '{}', '{}');\nCOMMIT;\n",
+                migration.version,
+                migration.name.replace('\'', "''"),
+                migration.checksum()
+            ));
+        }
+        Ok(script)
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn migrations() -> Vec<Migration> {
+        vec![
+            Migration::new(
+                2,
+                "add_email",
+                "ALTER TABLE users ADD COLUMN email TEXT;
+                 CREATE INDEX users_email ON users(email);",
+            ),
+            Migration::new(
+                1,
+                "create_users",
+                "CREATE TABLE users(id INTEGER PRIMARY KEY)",
+            ),
+        ]
+    }
+
+    #[test]
+    fn run_pending_migrations() {
+        let db = Client::in_memory().unwrap();
+        let migrator = Migrator::new(migrations()).unwrap();
+
+        let script = block_on(migrator.dry_run(&db)).unwrap();
+        assert!(script.starts_with("-- 1 (create_users)"));
+        assert!(script.contains("CREATE INDEX users_email ON users(email);"));
+        assert!(script.contains("VALUES (2, 'add_email', '"));
+        assert!(block_on(db.execute("SELECT * FROM users")).is_err());
+        // The dry run did not create the table recording migrations
+        let err = block_on(db.execute(format!("SELECT * FROM {MIGRATIONS_TABLE}"))).unwrap_err();
+        assert!(err.to_string().contains("no such table"), "{err}");
+
+        let applied = block_on(migrator.run(&db)).unwrap();
+        assert_eq!(
+            applied.iter().map(|m| m.version()).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        block_on(db.execute("SELECT id, email FROM users")).unwrap();
+        assert!(block_on(migrator.run(&db)).unwrap().is_empty());
+
+        let mut edited = migrations();
+        edited[0].sql.push_str(" -- edited");
+        let err = block_on(Migrator::new(edited).unwrap().run(&db)).unwrap_err();
+        assert!(err.to_string().contains("was edited"), "{err}");
+
+        let err = block_on(Migrator::new([]).unwrap().run(&db)).unwrap_err();
+        assert!(err.to_string().contains("is unknown"), "{err}");
+    }
+
+    // Runs a migration failing after its first statement, checks that none of it was
+    // applied, and that it can be applied once fixed.
+    async fn assert_failed_migration_rolled_back(db: &Client) {
+        let mut migrations = migrations();
+        migrations.push(Migration::new(
+            3,
+            "broken",
+            "CREATE TABLE t(x); INSERT INTO missing VALUES (1)",
+        ));
+        let migrator = Migrator::new(migrations).unwrap();
+
+        assert!(migrator.run(db).await.is_err());
+        assert!(db.execute("SELECT * FROM t").await.is_err());
+        let pending = migrator.pending(db).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].name(), "broken");
+
+        let mut migrations = self::migrations();
+        migrations.push(Migration::new(3, "fixed", "CREATE TABLE t(x)"));
+        let migrator = Migrator::new(migrations).unwrap();
+        assert_eq!(migrator.run(db).await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn failed_migration_is_rolled_back() {
+        let db = Client::in_memory().unwrap();
+        block_on(assert_failed_migration_rolled_back(&db));
+    }
+
+    #[cfg(feature = "reqwest_backend")]
+    #[tokio::test]
+    async fn failed_migration_is_rolled_back_over_http() {
+        let server = crate::http_test_server::TestServer::start_sql().await;
+        let db = Client::Http(server.client());
+        assert_failed_migration_rolled_back(&db).await;
+    }
+
+    #[cfg(feature = "hrana_backend")]
+    #[tokio::test]
+    async fn failed_migration_is_rolled_back_over_hrana() {
+        let server = crate::hrana_test_server::TestServer::start().await;
+        let db = Client::Hrana(crate::hrana::Client::new(server.url(), "").await.unwrap());
+        assert_failed_migration_rolled_back(&db).await;
+    }
+
+    #[test]
+    fn migrations_from_dir() {
+        let dir = std::env::temp_dir().join(format!("libsql_migrations_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("0002_add_email.sql"),
+            "ALTER TABLE users ADD email TEXT",
+        )
+        .unwrap();
+        std::fs::write(dir.join("0001_create_users.sql"), "CREATE TABLE users(id)").unwrap();
+        std::fs::write(dir.join("README.md"), "not a migration").unwrap();
+
+        let migrator = Migrator::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<_> = migrator.migrations().iter().map(|m| m.name()).collect();
+        assert_eq!(names, ["create_users", "add_email"]);
+        assert!(Migrator::new(
+            migrations()
+                .into_iter()
+                .chain([Migration::new(1, "again", "")])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn transaction_control_rejected() {
+        let db = Client::in_memory().unwrap();
+        let mut migrations = migrations();
+        migrations.push(Migration::new(
+            3,
+            "own_transaction",
+            "BEGIN; CREATE TABLE t(x); COMMIT;",
+        ));
+        let migrator = Migrator::new(migrations).unwrap();
+
+        let err = block_on(migrator.dry_run(&db)).unwrap_err();
+        assert!(
+            err.to_string().contains("cannot control transactions"),
+            "{err}"
+        );
+        let err = block_on(migrator.run(&db)).unwrap_err();
+        assert!(
+            err.to_string().contains("cannot control transactions"),
+            "{err}"
+        );
+        // No migration was applied
+        assert!(block_on(db.execute("SELECT * FROM users")).is_err());
+    }
+}
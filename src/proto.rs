@@ -1,12 +1,412 @@
 //! `proto` contains libSQL/sqld/hrana wire protocol.
 
 #[cfg(feature = "hrana_backend")]
-pub use hrana_client::proto::{
-    pipeline, Batch, BatchReq, BatchResp, BatchResult, ClientMsg, Col, Error, ExecuteReq,
-    ExecuteResp, OpenStreamReq, Request, Response, ServerMsg, Stmt, StmtResult, Value,
-};
+use hrana_client::proto as hrana_proto;
 #[cfg(not(feature = "hrana_backend"))]
-pub use hrana_client_proto::{
-    pipeline, Batch, BatchReq, BatchResp, BatchResult, ClientMsg, Col, Error, ExecuteReq,
-    ExecuteResp, OpenStreamReq, Request, Response, ServerMsg, Stmt, StmtResult, Value,
+use hrana_client_proto as hrana_proto;
+
+pub use crate::batch::BatchCond;
+pub use hrana_proto::{
+    BatchReq, BatchResp, ClientMsg, ExecuteReq, ExecuteResp, NamedArg, OpenStreamReq, Request,
+    Response, ServerMsg, Value,
 };
+
+/// Statement to execute.
+///
+/// Unlike the type of the underlying protocol crate, it may refer to SQL stored on the
+/// stream with a `store_sql` request by its id, instead of carrying the SQL text.
+#[derive(serde::Serialize, Debug)]
+pub struct Stmt {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sql: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sql_id: Option<i32>,
+    pub args: Vec<Value>,
+    pub named_args: Vec<NamedArg>,
+    pub want_rows: bool,
+}
+
+impl Stmt {
+    pub fn new(sql: impl Into<String>, want_rows: bool) -> Self {
+        Self {
+            sql: Some(sql.into()),
+            sql_id: None,
+            args: Vec::new(),
+            named_args: Vec::new(),
+            want_rows,
+        }
+    }
+
+    /// Creates a statement executing the SQL stored on the stream under `sql_id`
+    pub fn stored(sql_id: i32, want_rows: bool) -> Self {
+        Self {
+            sql: None,
+            sql_id: Some(sql_id),
+            args: Vec::new(),
+            named_args: Vec::new(),
+            want_rows,
+        }
+    }
+
+    pub fn bind(&mut self, val: Value) {
+        self.args.push(val);
+    }
+
+    pub fn bind_named(&mut self, name: String, value: Value) {
+        self.named_args.push(NamedArg { name, value });
+    }
+}
+
+/// Batch of statements, each one executed only if its condition holds.
+///
+/// Unlike the type of the underlying protocol crate, its conditions are serialized
+/// as the protocol expects them.
+#[derive(serde::Serialize, Debug, Default)]
+pub struct Batch {
+    steps: Vec<BatchStep>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(&mut self, condition: Option<BatchCond>, stmt: Stmt) {
+        self.steps.push(BatchStep { condition, stmt });
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct BatchStep {
+    condition: Option<BatchCond>,
+    stmt: Stmt,
+}
+
+/// Result of executing a statement.
+///
+/// Unlike the type of the underlying protocol crate, it keeps the declared type of the columns.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct StmtResult {
+    pub cols: Vec<Col>,
+    pub rows: Vec<Vec<Value>>,
+    pub affected_row_count: u64,
+    #[serde(default, deserialize_with = "option_i64_as_str")]
+    pub last_insert_rowid: Option<i64>,
+}
+
+/// Column of a [StmtResult].
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct Col {
+    pub name: Option<String>,
+    /// Declared type of the column, if it comes directly from a table.
+    #[serde(default)]
+    pub decltype: Option<String>,
+    /// Table the column comes from. Not part of the protocol, only the local backend knows it.
+    #[serde(skip)]
+    pub table: Option<String>,
+    /// Name of the column in its table. Not part of the protocol, only the local backend knows it.
+    #[serde(skip)]
+    pub origin: Option<String>,
+}
+
+// Integers are sent as strings, so that they survive JSON parsers which only have doubles.
+fn option_i64_as_str<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Option<i64>, D::Error> {
+    use serde::de::Error as _;
+
+    let value = <Option<&'de str> as serde::Deserialize>::deserialize(de)?;
+    value
+        .map(|s| {
+            s.parse().map_err(|_| {
+                D::Error::invalid_value(
+                    serde::de::Unexpected::Str(s),
+                    &"decimal integer as a string",
+                )
+            })
+        })
+        .transpose()
+}
+
+impl From<hrana_proto::StmtResult> for StmtResult {
+    fn from(result: hrana_proto::StmtResult) -> Self {
+        Self {
+            cols: result
+                .cols
+                .into_iter()
+                .map(|c| Col {
+                    name: c.name,
+                    ..Default::default()
+                })
+                .collect(),
+            rows: result.rows,
+            affected_row_count: result.affected_row_count,
+            last_insert_rowid: result.last_insert_rowid,
+        }
+    }
+}
+
+/// Error reported by the server for a request or for a single step of a batch.
+///
+/// Unlike the error type of the underlying protocol crate, it preserves the
+/// machine-readable `code` (e.g. `SQLITE_CONSTRAINT_UNIQUE`) sent by sqld.
+/// Errors received through the hrana client of the WebSocket backend have no `code`,
+/// because that client drops it.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<hrana_proto::Error> for Error {
+    fn from(e: hrana_proto::Error) -> Self {
+        Self {
+            message: e.message,
+            code: None,
+        }
+    }
+}
+
+/// Results of executing a batch, one entry per step.
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct BatchResult {
+    pub step_results: Vec<Option<StmtResult>>,
+    pub step_errors: Vec<Option<Error>>,
+}
+
+impl From<hrana_proto::BatchResult> for BatchResult {
+    fn from(result: hrana_proto::BatchResult) -> Self {
+        Self {
+            step_results: result
+                .step_results
+                .into_iter()
+                .map(|r| r.map(StmtResult::from))
+                .collect(),
+            step_errors: result
+                .step_errors
+                .into_iter()
+                .map(|e| e.map(Error::from))
+                .collect(),
+        }
+    }
+}
+
+/// Messages of the HTTP pipeline protocol.
+///
+/// <https://github.com/libsql/sqld/blob/main/docs/HTTP_V2_SPEC.md>
+pub mod pipeline {
+    use super::{Batch, BatchResult, Error, Stmt, StmtResult};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Debug)]
+    pub struct ClientMsg {
+        pub baton: Option<String>,
+        pub requests: Vec<StreamRequest>,
+    }
+
+    #[derive(Serialize, Debug)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum StreamRequest {
+        Close,
+        Execute(StreamExecuteReq),
+        Batch(StreamBatchReq),
+        StoreSql(StoreSqlReq),
+        CloseSql(CloseSqlReq),
+    }
+
+    #[derive(Serialize, Debug)]
+    pub struct StreamExecuteReq {
+        pub stmt: Stmt,
+    }
+
+    #[derive(Serialize, Debug)]
+    pub struct StreamBatchReq {
+        pub batch: Batch,
+    }
+
+    /// Stores SQL on the stream, so that statements can refer to it by `sql_id`.
+    #[derive(Serialize, Debug)]
+    pub struct StoreSqlReq {
+        pub sql_id: i32,
+        pub sql: String,
+    }
+
+    /// Removes SQL stored on the stream with [StoreSqlReq].
+    #[derive(Serialize, Debug)]
+    pub struct CloseSqlReq {
+        pub sql_id: i32,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ServerMsg {
+        pub baton: Option<String>,
+        pub base_url: Option<String>,
+        pub results: Vec<Response>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum Response {
+        Ok(StreamResponseOk),
+        Error(StreamResponseError),
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StreamResponseOk {
+        pub response: StreamResponse,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StreamResponseError {
+        pub error: Error,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum StreamResponse {
+        Close,
+        Execute(StreamExecuteResult),
+        Batch(StreamBatchResult),
+        StoreSql,
+        CloseSql,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StreamExecuteResult {
+        pub result: StmtResult,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StreamBatchResult {
+        pub result: BatchResult,
+    }
+}
+
+/// Messages of the cursor endpoint of the HTTP protocol, which streams the results
+/// of a batch as newline-delimited JSON.
+///
+/// <https://github.com/tursodatabase/libsql/blob/main/docs/HRANA_3_SPEC.md#execute-a-batch-using-a-cursor>
+pub mod cursor {
+    use super::{Batch, Col, Error, Value};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Debug)]
+    pub struct CursorReq {
+        pub baton: Option<String>,
+        pub batch: Batch,
+    }
+
+    /// First line of the response body, followed by one [CursorEntry] per line.
+    #[derive(Deserialize, Debug)]
+    pub struct CursorRespBody {
+        pub baton: Option<String>,
+        pub base_url: Option<String>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum CursorEntry {
+        StepBegin(StepBeginEntry),
+        StepEnd(StepEndEntry),
+        StepError(StepErrorEntry),
+        Row(RowEntry),
+        Error(ErrorEntry),
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StepBeginEntry {
+        pub step: u32,
+        pub cols: Vec<Col>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct StepEndEntry {}
+
+    #[derive(Deserialize, Debug)]
+    pub struct StepErrorEntry {
+        pub step: u32,
+        pub error: Error,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct RowEntry {
+        pub row: Vec<Value>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ErrorEntry {
+        pub error: Error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_step_error_keeps_code() {
+        let msg: pipeline::ServerMsg = serde_json::from_str(
+            r#"{"baton": null, "base_url": null, "results": [{"type": "ok", "response": {"type": "batch", "result": {
+                "step_results": [{"cols": [], "rows": [], "affected_row_count": 1, "last_insert_rowid": "1"}, null],
+                "step_errors": [null, {"message": "UNIQUE constraint failed: t.id", "code": "SQLITE_CONSTRAINT_UNIQUE"}]
+            }}}]}"#,
+        )
+        .unwrap();
+        let result = match msg.results.into_iter().next() {
+            Some(pipeline::Response::Ok(pipeline::StreamResponseOk {
+                response: pipeline::StreamResponse::Batch(batch),
+            })) => batch.result,
+            other => panic!("unexpected response: {other:?}"),
+        };
+        let error = result.step_errors[1].clone().unwrap();
+        assert_eq!(error.code.as_deref(), Some("SQLITE_CONSTRAINT_UNIQUE"));
+        let error = crate::Error::from(error);
+        assert!(error.is_constraint_violation());
+        assert_eq!(
+            error.sqlite_code(),
+            Some(crate::error::codes::SQLITE_CONSTRAINT_UNIQUE)
+        );
+    }
+
+    #[test]
+    fn cursor_entries() {
+        let entries: Vec<cursor::CursorEntry> = [
+            r#"{"type": "step_begin", "step": 0, "cols": [{"name": "x", "decltype": "INTEGER"}]}"#,
+            r#"{"type": "row", "row": [{"type": "integer", "value": "42"}]}"#,
+            r#"{"type": "step_end", "affected_row_count": 0, "last_insert_rowid": null}"#,
+            r#"{"type": "step_error", "step": 1, "error": {"message": "no such table: t", "code": "SQLITE_ERROR"}}"#,
+        ]
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+        assert!(
+            matches!(&entries[0], cursor::CursorEntry::StepBegin(b) if b.cols[0].name.as_deref() == Some("x"))
+        );
+        assert!(
+            matches!(&entries[1], cursor::CursorEntry::Row(r) if matches!(r.row[..], [Value::Integer { value: 42 }]))
+        );
+        assert!(matches!(entries[2], cursor::CursorEntry::StepEnd(_)));
+        assert!(
+            matches!(&entries[3], cursor::CursorEntry::StepError(e) if e.error.code.as_deref() == Some("SQLITE_ERROR"))
+        );
+    }
+
+    #[test]
+    fn stmt_result_keeps_decltype() {
+        let result: StmtResult = serde_json::from_str(
+            r#"{"cols": [{"name": "id", "decltype": "INTEGER"}, {"name": "1+1"}],
+                "rows": [], "affected_row_count": 0, "last_insert_rowid": null}"#,
+        )
+        .unwrap();
+        let rs = crate::ResultSet::from(result);
+        assert_eq!(rs.columns, ["id", "1+1"]);
+        assert_eq!(rs.column_info()[0].decltype.as_deref(), Some("INTEGER"));
+        assert_eq!(rs.column_info()[1].decltype, None);
+        assert_eq!(rs.column_info()[0].table, None);
+    }
+}
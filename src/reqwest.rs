@@ -1,6 +1,10 @@
-use anyhow::Result;
+use std::time::Duration;
 
+use futures::StreamExt;
+
+use crate::http::BodyStream;
 use crate::proto::pipeline;
+use crate::{Error, Result, Timeouts};
 
 #[derive(Clone, Debug)]
 pub struct HttpClient {
@@ -9,33 +13,81 @@ pub struct HttpClient {
 
 impl HttpClient {
     pub fn new() -> Self {
+        Self::with_timeouts(&Timeouts::default())
+    }
+
+    /// Creates a client which gives up connecting after `timeouts.connect`.
+    /// The request timeout is passed to [HttpClient::send] instead.
+    pub fn with_timeouts(timeouts: &Timeouts) -> Self {
+        let mut builder = reqwest::Client::builder();
+        if let Some(connect) = timeouts.connect {
+            builder = builder.connect_timeout(connect);
+        }
         Self {
-            inner: reqwest::Client::new(),
+            // Same as reqwest::Client::new(), which also panics if TLS cannot be initialized
+            inner: builder
+                .build()
+                .expect("Failed to initialize reqwest client"),
         }
     }
 
-    pub async fn send(
+    // Posts `body` to `url`, failing if the server does not answer with 200 OK.
+    async fn post(
         &self,
         url: String,
         auth: String,
         body: String,
-    ) -> Result<pipeline::ServerMsg> {
-        let response = self
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response> {
+        let mut request = self
             .inner
             .post(url)
             .body(body)
-            .header("Authorization", auth)
-            .send()
-            .await?;
+            .header("Authorization", auth);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let response = request.send().await?;
         if response.status() != reqwest::StatusCode::OK {
             let status = response.status();
-            let txt = response.text().await.unwrap_or_default();
-            anyhow::bail!("{status}: {txt}");
+            let message = response.text().await.unwrap_or_default();
+            return Err(Error::Http {
+                status: status.as_u16(),
+                message,
+            });
         }
+        Ok(response)
+    }
+
+    pub async fn send(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+        timeout: Option<Duration>,
+    ) -> Result<pipeline::ServerMsg> {
+        let response = self.post(url, auth, body, timeout).await?;
         let resp: String = response.text().await?;
         let response: pipeline::ServerMsg = serde_json::from_str(&resp)?;
         Ok(response)
     }
+
+    /// Like [HttpClient::send], but returns the response body as it arrives.
+    pub async fn send_streaming(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+    ) -> Result<BodyStream> {
+        let response = self.post(url, auth, body, None).await?;
+        Ok(
+            futures::stream::try_unfold(response, |mut response| async move {
+                let chunk = response.chunk().await?;
+                Ok(chunk.map(|chunk| (chunk.to_vec(), response)))
+            })
+            .boxed(),
+        )
+    }
 }
 
 impl Default for HttpClient {
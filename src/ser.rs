@@ -0,0 +1,478 @@
+//! libsql serialization utilities.
+
+use serde::de::value::Error as SerError;
+use serde::ser::{
+    Error as _, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer,
+};
+
+use crate::Value;
+
+/// Serialize any type `T` that implements [`serde::Serialize`] into named arguments,
+/// one for each field, named after the field with a `:` prefix.
+///
+/// This is the counterpart of [`de::from_row`](crate::de::from_row), see
+/// [`Statement::with_params`](crate::Statement::with_params) for a convenient way to use it.
+///
+/// # Types
+///
+/// `T` must be a struct or a map with string keys. Its fields may be of the following types:
+///
+/// - String, &str, char
+/// - Vec<u8>, &[u8]
+/// - integers (u64 only if it fits in an i64)
+/// - floats
+/// - bool, stored as 0 or 1
+/// - Option<T> (where T is any of the above)
+/// - ()
+/// - unit enum variants, stored as their name
+///
+/// # Example
+///
+/// ```
+/// # fn run() -> anyhow::Result<()> {
+/// use libsql_client::ser;
+///
+/// #[derive(serde::Serialize)]
+/// struct User {
+///     name: String,
+///     email: String,
+///     age: i64,
+/// }
+///
+/// let user = User {
+///     name: "Alice".into(),
+///     email: "alice@example.com".into(),
+///     age: 42,
+/// };
+/// let args = ser::to_named_args(&user)?;
+/// assert_eq!(args[0].0, ":name");
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_named_args<T: Serialize + ?Sized>(value: &T) -> crate::Result<Vec<(String, Value)>> {
+    value.serialize(Args).map_err(Into::into)
+}
+
+/// Serializes the top-level struct or map into named arguments.
+struct Args;
+
+macro_rules! expects_struct {
+    ($($method:ident($($ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+                Err(SerError::custom("Expects a struct"))
+            }
+        )*
+    };
+}
+
+impl Serializer for Args {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+    type SerializeSeq = Impossible<Self::Ok, SerError>;
+    type SerializeTuple = Impossible<Self::Ok, SerError>;
+    type SerializeTupleStruct = Impossible<Self::Ok, SerError>;
+    type SerializeTupleVariant = Impossible<Self::Ok, SerError>;
+    type SerializeMap = ArgsMap;
+    type SerializeStruct = ArgsMap;
+    type SerializeStructVariant = Impossible<Self::Ok, SerError>;
+
+    expects_struct! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(ArgsMap {
+            args: Vec::with_capacity(len.unwrap_or_default()),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(ArgsMap {
+            args: Vec::with_capacity(len),
+            key: None,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(SerError::custom("Expects a struct"))
+    }
+}
+
+struct ArgsMap {
+    args: Vec<(String, Value)>,
+    key: Option<String>,
+}
+
+impl SerializeStruct for ArgsMap {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.args.push((format!(":{key}"), value.serialize(V)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.args)
+    }
+}
+
+impl SerializeMap for ArgsMap {
+    type Ok = Vec<(String, Value)>;
+    type Error = SerError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        match key.serialize(V)? {
+            Value::Text { value } => {
+                self.key = Some(value);
+                Ok(())
+            }
+            _ => Err(SerError::custom("Map keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.args.push((format!(":{key}"), value.serialize(V)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.args)
+    }
+}
+
+/// Serializes a single field into a [`Value`].
+struct V;
+
+impl Serializer for V {
+    type Ok = Value;
+    type Error = SerError;
+    type SerializeSeq = Blob;
+    type SerializeTuple = Impossible<Value, SerError>;
+    type SerializeTupleStruct = Impossible<Value, SerError>;
+    type SerializeTupleVariant = Impossible<Value, SerError>;
+    type SerializeMap = Impossible<Value, SerError>;
+    type SerializeStruct = Impossible<Value, SerError>;
+    type SerializeStructVariant = Impossible<Value, SerError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerError> {
+        Ok(Value::Integer { value: v as i64 })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, SerError> {
+        Ok(Value::Integer { value: v })
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, SerError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, SerError> {
+        let v = i64::try_from(v)
+            .map_err(|_| SerError::custom(format!("{v} does not fit in a 64-bit integer")))?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, SerError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, SerError> {
+        Ok(Value::Float { value: v })
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, SerError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, SerError> {
+        Ok(Value::Text {
+            value: v.to_string(),
+        })
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerError> {
+        Ok(Value::Blob { value: v.to_vec() })
+    }
+
+    fn serialize_none(self) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SerError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, SerError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _value: &T,
+    ) -> Result<Value, SerError> {
+        Err(SerError::custom(format!(
+            "Unsupported enum variant with data: {variant}"
+        )))
+    }
+
+    // Vec<u8> is serialized as a sequence, which is stored as a blob like in `de`
+    fn serialize_seq(self, len: Option<usize>) -> Result<Blob, SerError> {
+        Ok(Blob(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerError> {
+        Err(SerError::custom("Unsupported type: tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerError> {
+        Err(SerError::custom("Unsupported type: tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        Err(SerError::custom("Unsupported type: tuple variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        Err(SerError::custom("Unsupported type: map"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerError> {
+        Err(SerError::custom("Unsupported type: struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        Err(SerError::custom("Unsupported type: struct variant"))
+    }
+}
+
+/// Collects a sequence of bytes into a blob.
+struct Blob(Vec<u8>);
+
+impl SerializeSeq for Blob {
+    type Ok = Value;
+    type Error = SerError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerError> {
+        match value.serialize(V)? {
+            Value::Integer { value } => {
+                let byte = u8::try_from(value)
+                    .map_err(|_| SerError::custom("Only sequences of bytes are supported"))?;
+                self.0.push(byte);
+                Ok(())
+            }
+            _ => Err(SerError::custom("Only sequences of bytes are supported")),
+        }
+    }
+
+    fn end(self) -> Result<Value, SerError> {
+        Ok(Value::Blob { value: self.0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    enum Kind {
+        Admin,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Foo {
+        bar: String,
+        baf: f64,
+        baz: i64,
+        bab: Vec<u8>,
+        ban: (),
+        bad: Option<i64>,
+        bac: Option<f64>,
+        bool: bool,
+        kind: Kind,
+    }
+
+    #[test]
+    fn struct_to_named_args() {
+        let foo = Foo {
+            bar: "foo".into(),
+            baf: 42.0,
+            baz: 42,
+            bab: vec![6u8; 128],
+            ban: (),
+            bad: Some(42),
+            bac: None,
+            bool: true,
+            kind: Kind::Admin,
+        };
+        let args: HashMap<String, Value> = to_named_args(&foo).unwrap().into_iter().collect();
+
+        assert!(matches!(&args[":bar"], Value::Text { value } if value == "foo"));
+        assert!(matches!(args[":baf"], Value::Float { value } if value > 41.0));
+        assert!(matches!(args[":baz"], Value::Integer { value: 42 }));
+        assert!(matches!(&args[":bab"], Value::Blob { value } if value == &vec![6u8; 128]));
+        assert!(matches!(args[":ban"], Value::Null));
+        assert!(matches!(args[":bad"], Value::Integer { value: 42 }));
+        assert!(matches!(args[":bac"], Value::Null));
+        assert!(matches!(args[":bool"], Value::Integer { value: 1 }));
+        assert!(matches!(&args[":kind"], Value::Text { value } if value == "Admin"));
+    }
+
+    #[test]
+    fn unsupported_types() {
+        assert!(to_named_args(&42).is_err());
+        assert!(to_named_args(&HashMap::from([("a", vec!["b"])])).is_err());
+        assert!(to_named_args(&HashMap::from([("a", u64::MAX)])).is_err());
+    }
+}
@@ -1,31 +1,133 @@
-use anyhow::Result;
+use std::time::Duration;
 
+use futures::{SinkExt, StreamExt, TryStreamExt};
+use spin_sdk::http::conversions::TryIntoOutgoingRequest;
+use spin_sdk::http::IncomingResponse;
+use spin_sdk::wit::wasi::http::outgoing_handler;
+use spin_sdk::wit::wasi::http::types::RequestOptions;
+use spin_sdk::wit::wasi::io::poll;
+
+use crate::http::BodyStream;
 use crate::proto::pipeline;
+use crate::{Error, Result, Timeouts};
 
 #[derive(Clone, Debug)]
-pub struct HttpClient;
+pub struct HttpClient {
+    connect_timeout: Option<Duration>,
+}
+
+// Converts a timeout to the milliseconds expected by wasi-http
+fn millis(timeout: Option<Duration>) -> Option<u32> {
+    timeout.map(|t| t.as_millis().try_into().unwrap_or(u32::MAX))
+}
 
 impl HttpClient {
     pub fn new() -> Self {
-        Self
+        Self::with_timeouts(&Timeouts::default())
     }
 
-    pub async fn send(
+    /// Creates a client which gives up connecting after `timeouts.connect`.
+    /// The request timeout is passed to [HttpClient::send] instead.
+    pub fn with_timeouts(timeouts: &Timeouts) -> Self {
+        Self {
+            connect_timeout: timeouts.connect,
+        }
+    }
+
+    // Posts `body` to `url`, failing if the server does not answer with 200 OK.
+    async fn post(
         &self,
         url: String,
         auth: String,
         body: String,
-    ) -> Result<pipeline::ServerMsg> {
+        timeout: Option<Duration>,
+    ) -> Result<IncomingResponse> {
         let req = http::Request::builder()
             .uri(&url)
             .header("Authorization", &auth)
             .method("POST")
-            .body(Some(bytes::Bytes::copy_from_slice(body.as_bytes())))?;
+            .body(Some(bytes::Bytes::copy_from_slice(body.as_bytes())))
+            .map_err(|e| Error::Transport(Box::new(e)))?;
+
+        // spin_sdk::http::send() does not accept request options, so the request is sent
+        // directly through wasi-http, which is what enforces the timeouts
+        let (request, body) = req
+            .try_into_outgoing_request()
+            .map_err(|e| Error::Transport(e.into()))?;
+        let options = RequestOptions {
+            connect_timeout_ms: millis(self.connect_timeout),
+            first_byte_timeout_ms: millis(timeout),
+            between_bytes_timeout_ms: millis(timeout),
+        };
+        let mut body_sink = request.take_body();
+        let response = outgoing_handler::handle(request, Some(options))
+            .map_err(|e| Error::Transport(e.to_string().into()))?;
+        if let Some(body) = body {
+            body_sink
+                .send(body)
+                .await
+                .map_err(|e| Error::Transport(e.to_string().into()))?;
+        }
+        drop(body_sink);
+        let response = loop {
+            match response.get() {
+                Some(response) => break response,
+                None => poll::poll_one(&response.subscribe()),
+            }
+        };
+        let response = response
+            .map_err(|()| Error::Protocol("HTTP response was already consumed".into()))?
+            .map_err(|e| match e {
+                spin_sdk::http::Error::TimeoutError(e) => Error::Timeout(e),
+                e => Error::Transport(e.to_string().into()),
+            })?;
+
+        if response.status() != 200 {
+            let status = response.status();
+            let message = response.into_body().await.unwrap_or_default();
+            return Err(Error::Http {
+                status,
+                message: String::from_utf8_lossy(&message).into_owned(),
+            });
+        }
+        Ok(response)
+    }
 
-        let response: http::Response<String> = spin_sdk::http::send(req).await?;
-        let response: pipeline::ServerMsg = serde_json::from_str(&response.into_body())?;
+    pub async fn send(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+        timeout: Option<Duration>,
+    ) -> Result<pipeline::ServerMsg> {
+        let response = self.post(url, auth, body, timeout).await?;
+        let body = response
+            .into_body()
+            .await
+            .map_err(|e| Error::Transport(e.to_debug_string().into()))?;
+        let response: pipeline::ServerMsg = serde_json::from_slice(&body)?;
         Ok(response)
     }
+
+    /// Like [HttpClient::send], but returns the response body as it arrives.
+    pub async fn send_streaming(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+    ) -> Result<BodyStream> {
+        let response = self.post(url, auth, body, None).await?;
+        let stream = response
+            .take_body_stream()
+            .map_err(|e| Error::Transport(e.to_debug_string().into()));
+        // Dropping the response cancels the request, so it's kept alive along with its body
+        Ok(stream
+            .map(move |chunk| {
+                let _ = &response;
+                chunk
+            })
+            .boxed())
+    }
 }
 
 impl Default for HttpClient {
@@ -4,12 +4,26 @@
 use base64::prelude::BASE64_STANDARD_NO_PAD;
 use base64::Engine;
 
-use crate::Value;
+use crate::{ToValue, Value};
 
 /// SQL statement, possibly with bound parameters
+#[derive(Clone)]
 pub struct Statement {
     pub(crate) sql: String,
     pub(crate) args: Vec<Value>,
+    pub(crate) named_args: Vec<(String, Value)>,
+    pub(crate) idempotent: bool,
+    // Only the local and HTTP backends treat prepared statements differently
+    #[cfg_attr(
+        not(any(
+            feature = "local_backend",
+            feature = "reqwest_backend",
+            feature = "workers_backend",
+            feature = "spin_backend"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) prepared: bool,
 }
 
 impl Statement {
@@ -24,6 +38,9 @@ impl Statement {
         Self {
             sql: q.into(),
             args: vec![],
+            named_args: vec![],
+            idempotent: false,
+            prepared: false,
         }
     }
 
@@ -34,10 +51,169 @@ impl Statement {
     /// ```
     /// let stmt = libsql_client::Statement::with_args("UPDATE t SET x = ? WHERE key = ?", &[3, 8]);
     /// ```
-    pub fn with_args(q: impl Into<String>, params: &[impl Into<Value> + Clone]) -> Statement {
+    pub fn with_args(q: impl Into<String>, params: &[impl ToValue]) -> Statement {
         Self {
             sql: q.into(),
-            args: params.iter().map(|p| p.clone().into()).collect(),
+            args: params.iter().map(ToValue::to_value).collect(),
+            named_args: vec![],
+            idempotent: false,
+            prepared: false,
+        }
+    }
+
+    /// Creates a statement with parameters bound by name
+    ///
+    /// Names may start with `:`, `@` or `$`, matching the placeholder used in the SQL text.
+    /// Names without one of these prefixes match a placeholder with any of them.
+    /// Executing a statement whose SQL has no placeholder for one of the names fails with
+    /// a [`SQLITE_RANGE`](crate::error::codes::SQLITE_RANGE) error, as does binding both
+    /// positional and named arguments.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// let stmt = libsql_client::Statement::with_named_args(
+    ///     "UPDATE t SET x = :x WHERE key = $key",
+    ///     &[(":x", 3), ("$key", 8)],
+    /// );
+    /// let params = HashMap::from([("x", "three")]);
+    /// let stmt = libsql_client::Statement::with_named_args("UPDATE t SET x = :x", params);
+    /// ```
+    pub fn with_named_args(
+        q: impl Into<String>,
+        params: impl IntoIterator<Item = impl NamedArg>,
+    ) -> Statement {
+        Self {
+            named_args: params.into_iter().map(|p| p.into_named_arg()).collect(),
+            ..Self::new(q)
+        }
+    }
+
+    /// Creates a statement with parameters bound by name from the fields of a struct,
+    /// see [`ser::to_named_args`](crate::ser::to_named_args) for the supported types.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn run() -> anyhow::Result<()> {
+    /// #[derive(serde::Serialize)]
+    /// struct User {
+    ///     name: String,
+    ///     email: String,
+    ///     age: i64,
+    /// }
+    ///
+    /// let user = User { name: "Alice".into(), email: "alice@example.com".into(), age: 42 };
+    /// let stmt = libsql_client::Statement::with_params(
+    ///     "INSERT INTO users VALUES (:name, :email, :age)",
+    ///     &user,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_params(
+        q: impl Into<String>,
+        params: &(impl serde::Serialize + ?Sized),
+    ) -> crate::Result<Statement> {
+        Ok(Self {
+            named_args: crate::ser::to_named_args(params)?,
+            ..Self::new(q)
+        })
+    }
+
+    /// Marks the statement as safe to repeat, so that it can be retried after a transient
+    /// failure even though it writes to the database, see [RetryPolicy](crate::RetryPolicy).
+    /// Read-only statements are detected automatically and do not need to be marked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let stmt = libsql_client::Statement::new("INSERT OR IGNORE INTO t VALUES (1)").idempotent();
+    /// ```
+    pub fn idempotent(mut self) -> Statement {
+        self.idempotent = true;
+        self
+    }
+}
+
+/// A `(name, value)` pair bound to a named parameter, see [Statement::with_named_args]
+pub trait NamedArg {
+    fn into_named_arg(self) -> (String, Value);
+}
+
+impl<K: Into<String>, V: ToValue> NamedArg for (K, V) {
+    fn into_named_arg(self) -> (String, Value) {
+        (self.0.into(), self.1.to_value())
+    }
+}
+
+impl<K: Into<String> + Clone, V: ToValue> NamedArg for &(K, V) {
+    fn into_named_arg(self) -> (String, Value) {
+        (self.0.clone().into(), self.1.to_value())
+    }
+}
+
+/// SQL statement prepared once with [Client::prepare](crate::Client::prepare),
+/// to be executed many times with different arguments.
+///
+/// The local backend keeps the compiled statement around and reuses it instead of
+/// parsing the SQL again, for the most recently used statements. The HTTP backends
+/// store the SQL on the stream it is executed on the first time, and then only send
+/// its id, in a transaction or not. The WebSocket backend still sends the SQL text with each
+/// statement, because the hrana client it uses does not support storing SQL.
+///
+/// # Examples
+///
+/// ```
+/// # async fn f() -> libsql_client::Result<()> {
+/// let db = libsql_client::Client::in_memory()?;
+/// db.execute("CREATE TABLE t(x)").await?;
+/// let insert = db.prepare("INSERT INTO t VALUES (?)").await?;
+/// for i in 0..10 {
+///     db.execute(insert.bind(&[i])).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct PreparedStatement {
+    sql: String,
+}
+
+impl PreparedStatement {
+    pub(crate) fn new(sql: String) -> Self {
+        Self { sql }
+    }
+
+    /// SQL text of the statement
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Creates a statement to execute, with the given bound parameters
+    pub fn bind(&self, params: &[impl ToValue]) -> Statement {
+        Statement {
+            prepared: true,
+            ..Statement::with_args(self.sql.clone(), params)
+        }
+    }
+
+    /// Creates a statement to execute, with parameters bound by name,
+    /// see [Statement::with_named_args]
+    pub fn bind_named(&self, params: impl IntoIterator<Item = impl NamedArg>) -> Statement {
+        Statement {
+            prepared: true,
+            ..Statement::with_named_args(self.sql.clone(), params)
+        }
+    }
+}
+
+impl From<&PreparedStatement> for Statement {
+    fn from(stmt: &PreparedStatement) -> Statement {
+        Statement {
+            prepared: true,
+            ..Statement::new(stmt.sql.clone())
         }
     }
 }
@@ -47,6 +223,9 @@ impl From<String> for Statement {
         Statement {
             sql: q,
             args: vec![],
+            named_args: vec![],
+            idempotent: false,
+            prepared: false,
         }
     }
 }
@@ -63,24 +242,35 @@ impl From<&&str> for Statement {
     }
 }
 
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Blob { value } => serde_json::json!({
+            "base64": BASE64_STANDARD_NO_PAD.encode(value),
+        })
+        .to_string(),
+        _ => serde_json::json!(value)["value"].to_string(),
+    }
+}
+
 impl std::fmt::Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let params: Vec<String> = self
-            .args
-            .iter()
-            .map(|p| match p {
-                Value::Blob { value } => serde_json::json!({
-                    "base64": BASE64_STANDARD_NO_PAD.encode(value),
-                })
-                .to_string(),
-                _ => serde_json::json!(p)["value"].to_string(),
-            })
-            .collect();
+        let params: Vec<String> = self.args.iter().map(display_value).collect();
         write!(
             f,
-            "{{\"sql\": {}, \"args\": [{}]}}",
+            "{{\"sql\": {}, \"args\": [{}]",
             serde_json::json!(self.sql),
             params.join(",")
-        )
+        )?;
+        if !self.named_args.is_empty() {
+            let named_params: Vec<String> = self
+                .named_args
+                .iter()
+                .map(|(name, value)| {
+                    format!("{}: {}", serde_json::json!(name), display_value(value))
+                })
+                .collect();
+            write!(f, ", \"named_args\": {{{}}}", named_params.join(","))?;
+        }
+        f.write_str("}")
     }
 }
@@ -0,0 +1,74 @@
+//! Execution of the requests received by the test servers, on a database of the local backend.
+//!
+//! Statements and batches are given and answered in the JSON form of the Hrana protocol,
+//! including the conditions of batch steps, so that the test servers behave like sqld.
+
+use serde_json::{json, Value as Json};
+
+use crate::{Statement, Value};
+
+/// Executes a statement and returns its result, or the message of its error.
+pub(crate) fn execute(db: &crate::local::Client, stmt: &Json) -> Result<Json, String> {
+    // Values borrow their integers as strings, so they are parsed from JSON text
+    let value = |json: &Json| -> Value { serde_json::from_str(&json.to_string()).unwrap() };
+    let mut statement = Statement::new(stmt["sql"].as_str().unwrap());
+    for arg in stmt["args"].as_array().into_iter().flatten() {
+        statement.args.push(value(arg));
+    }
+    for arg in stmt["named_args"].as_array().into_iter().flatten() {
+        let value = value(&arg["value"]);
+        statement
+            .named_args
+            .push((arg["name"].as_str().unwrap().to_string(), value));
+    }
+    let result = db.execute(statement).map_err(|e| e.to_string())?;
+    Ok(json!({
+        "cols": result.columns.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>(),
+        "rows": result.rows.iter().map(|row| &row.values).collect::<Vec<_>>(),
+        "affected_row_count": result.rows_affected,
+        "last_insert_rowid": result.last_insert_rowid.map(|id| id.to_string()),
+    }))
+}
+
+/// Executes the steps of a batch whose conditions hold and returns the batch result.
+pub(crate) fn batch(db: &crate::local::Client, batch: &Json) -> Json {
+    let mut outcomes = Vec::new();
+    let mut step_results = Vec::new();
+    let mut step_errors = Vec::new();
+    for step in batch["steps"].as_array().unwrap() {
+        if !holds(&step["condition"], &outcomes) {
+            outcomes.push(None);
+            step_results.push(Json::Null);
+            step_errors.push(Json::Null);
+            continue;
+        }
+        match execute(db, &step["stmt"]) {
+            Ok(result) => {
+                outcomes.push(Some(true));
+                step_results.push(result);
+                step_errors.push(Json::Null);
+            }
+            Err(message) => {
+                outcomes.push(Some(false));
+                step_results.push(Json::Null);
+                step_errors.push(json!({ "message": message }));
+            }
+        }
+    }
+    json!({"step_results": step_results, "step_errors": step_errors})
+}
+
+// Evaluates the condition of a step, given the outcomes of the previous ones.
+fn holds(cond: &Json, outcomes: &[Option<bool>]) -> bool {
+    let outcome = |cond: &Json| outcomes[cond["step"].as_u64().unwrap() as usize];
+    let conds = |cond: &Json| cond["conds"].as_array().unwrap().clone();
+    match cond["type"].as_str() {
+        None => true,
+        Some("ok") => outcome(cond) == Some(true),
+        Some("error") => outcome(cond) == Some(false),
+        Some("not") => !holds(&cond["cond"], outcomes),
+        Some("and") => conds(cond).iter().all(|c| holds(c, outcomes)),
+        Some("or") => conds(cond).iter().any(|c| holds(c, outcomes)),
+        other => panic!("unexpected condition {other:?}"),
+    }
+}
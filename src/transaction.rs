@@ -1,19 +1,115 @@
 //! `Transaction` is a structure representing an interactive transaction.
+//!
+//! Transactions are generic over the handle they hold to their client: a [`Transaction`]
+//! borrows it, while an [`OwnedTransaction`] shares it through an [`Arc`], so that it can
+//! be moved into spawned tasks. [`SyncTransaction`] and [`SyncOwnedTransaction`] are their
+//! synchronous flavors, for a [`SyncClient`].
+//!
+//! A transaction dropped without being committed or rolled back is rolled back,
+//! and a warning is logged.
+//!
+//! Transactions can be nested with savepoints, see [`Tx::savepoint`].
 
-use crate::{Client, ResultSet, Statement, SyncClient};
-use anyhow::Result;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
 
-pub struct Transaction<'a> {
+use futures::executor::block_on;
+
+use crate::error::codes;
+use crate::{Client, Error, Result, ResultSet, Statement, SyncClient};
+
+/// How a transaction locks the database, see [`Client::transaction_with`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransactionBehavior {
+    /// The database is locked by the first statement of the transaction, like a bare `BEGIN`
+    #[default]
+    Deferred,
+    /// The database is locked for writing right away, with `BEGIN IMMEDIATE`,
+    /// so later writes cannot fail because another connection started writing
+    Immediate,
+    /// The database is locked right away with `BEGIN EXCLUSIVE`
+    Exclusive,
+    /// Only statements reading from the database are allowed, and the others are rejected
+    /// before being sent to the database, see [`Config::read_replica`](crate::Config::read_replica).
+    /// The transaction starts with `BEGIN DEFERRED`, on the read replica of the database
+    /// if the client has one.
+    ReadOnly,
+}
+
+impl TransactionBehavior {
+    fn begin(self) -> Statement {
+        Statement::new(match self {
+            TransactionBehavior::Deferred | TransactionBehavior::ReadOnly => "BEGIN DEFERRED",
+            TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
+            TransactionBehavior::Exclusive => "BEGIN EXCLUSIVE",
+        })
+    }
+}
+
+// Rejects statements which may write to the database, in read-only transactions.
+fn check_read_only(read_only: bool, stmt: &Statement) -> Result<()> {
+    if read_only && !crate::utils::is_read_only(&stmt.sql) {
+        return Err(Error::Sqlite {
+            code: Some(codes::SQLITE_READONLY),
+            message: format!("cannot execute `{}` in a read-only transaction", stmt.sql),
+        });
+    }
+    Ok(())
+}
+
+/// An interactive transaction, holding a handle `C` to its client.
+///
+/// See [`Transaction`] and [`OwnedTransaction`].
+pub struct Tx<C: Deref<Target = Client>> {
     pub(crate) id: u64,
-    pub(crate) client: &'a Client,
+    pub(crate) client: C,
+    dropped: DroppedSavepoints,
+    read_only: bool,
+    finished: bool,
 }
 
-impl<'a> Transaction<'a> {
-    pub async fn new(client: &'a Client, id: u64) -> Result<Transaction<'a>> {
-        client
-            .execute_in_transaction(id, Statement::from("BEGIN"))
-            .await?;
-        Ok(Self { id, client })
+/// An interactive transaction borrowing its client, see [`Client::transaction`]
+pub type Transaction<'a> = Tx<&'a Client>;
+
+/// An interactive transaction owning a handle to its client, see [`Client::owned_transaction`].
+///
+/// Unlike [`Transaction`], it does not borrow the client, so it can be moved
+/// into spawned tasks or kept in the state of a web request.
+///
+/// # Example
+///
+/// ```rust,no_run
+///   # async fn f() -> anyhow::Result<()> {
+///   # use crate::libsql_client::{Statement, args};
+///   let db = std::sync::Arc::new(libsql_client::Client::from_env().await?);
+///   let tx = db.owned_transaction().await?;
+///   tokio::spawn(async move {
+///     tx.execute(Statement::with_args("INSERT INTO users (name) VALUES (?)", args!["John"])).await?;
+///     tx.commit().await
+///   })
+///   .await??;
+///   # Ok(())
+///   # }
+/// ```
+pub type OwnedTransaction = Tx<Arc<Client>>;
+
+impl<C: Deref<Target = Client>> Tx<C> {
+    pub async fn new(client: C, id: u64) -> Result<Self> {
+        Self::with_behavior(client, id, TransactionBehavior::default()).await
+    }
+
+    pub async fn with_behavior(client: C, id: u64, behavior: TransactionBehavior) -> Result<Self> {
+        if behavior == TransactionBehavior::ReadOnly {
+            client.use_read_replica(id).await?;
+        }
+        client.execute_in_transaction(id, behavior.begin()).await?;
+        Ok(Self {
+            id,
+            client,
+            dropped: DroppedSavepoints::default(),
+            read_only: behavior == TransactionBehavior::ReadOnly,
+            finished: false,
+        })
     }
 
     /// Executes a statement within the current transaction.
@@ -35,31 +131,100 @@ impl<'a> Transaction<'a> {
     ///   # }
     /// ```
     pub async fn execute(&self, stmt: impl Into<Statement>) -> Result<ResultSet> {
-        self.client
-            .execute_in_transaction(self.id, stmt.into())
-            .await
+        let stmt = stmt.into();
+        check_read_only(self.read_only, &stmt)?;
+        self.dropped.execute(&self.client, self.id, stmt).await
+    }
+
+    /// Executes several statements within the current transaction,
+    /// in a single request to databases reached over HTTP.
+    ///
+    /// If a statement fails, [`Error::Batch`] reports which one, and the statements
+    /// following it are not executed, whatever the backend. The statements before it
+    /// are not undone, so the transaction should usually be rolled back.
+    pub async fn batch(
+        &self,
+        stmts: impl IntoIterator<Item = impl Into<Statement>>,
+    ) -> Result<Vec<ResultSet>> {
+        let stmts = batch_statements(self.read_only, stmts)?;
+        self.dropped.batch(&self.client, self.id, stmts).await
+    }
+
+    /// Starts a nested transaction, with a savepoint named `name`.
+    pub async fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        Savepoint::new(&self.client, self.id, &self.dropped, name, self.read_only).await
     }
 
     /// Commits the transaction to the database.
-    pub async fn commit(self) -> Result<()> {
-        self.client.commit_transaction(self.id).await
+    pub async fn commit(mut self) -> Result<()> {
+        self.dropped.roll_back(&self.client, self.id).await?;
+        self.client.commit_transaction(self.id).await?;
+        self.finished = true;
+        Ok(())
     }
 
     /// Rolls back the transaction, cancelling any of its side-effects.
-    pub async fn rollback(self) -> Result<()> {
-        self.client.rollback_transaction(self.id).await
+    pub async fn rollback(mut self) -> Result<()> {
+        self.client.rollback_transaction(self.id).await?;
+        self.finished = true;
+        Ok(())
     }
 }
 
-pub struct SyncTransaction<'a> {
-    pub(crate) id: u64,
-    pub(crate) client: &'a SyncClient,
+impl<C: Deref<Target = Client>> Drop for Tx<C> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.client.drop_transaction(self.id);
+        }
+    }
+}
+
+/// Handle to the asynchronous client wrapped by a synchronous one.
+struct SyncHandle<C>(C);
+
+impl<C: Deref<Target = SyncClient>> Deref for SyncHandle<C> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.0.inner
+    }
 }
 
-impl<'a> SyncTransaction<'a> {
-    pub fn new(client: &'a SyncClient, id: u64) -> Result<SyncTransaction<'a>> {
-        client.execute_in_transaction(id, Statement::from("BEGIN"))?;
-        Ok(Self { id, client })
+/// A synchronous flavor of [`Tx`], holding a handle `C` to its client.
+///
+/// See [`SyncTransaction`] and [`SyncOwnedTransaction`].
+pub struct SyncTx<C: Deref<Target = SyncClient>>(Tx<SyncHandle<C>>);
+
+/// A synchronous flavor of [`Transaction`], see [`SyncClient::transaction`]
+pub type SyncTransaction<'a> = SyncTx<&'a SyncClient>;
+
+/// A synchronous flavor of [`OwnedTransaction`], see [`SyncClient::owned_transaction`].
+///
+/// # Example
+///
+/// ```rust,no_run
+///   # fn f() -> anyhow::Result<()> {
+///   # use crate::libsql_client::{Statement, args};
+///   let db = std::sync::Arc::new(libsql_client::SyncClient::from_env()?);
+///   let tx = db.owned_transaction()?;
+///   std::thread::spawn(move || {
+///     tx.execute(Statement::with_args("INSERT INTO users (name) VALUES (?)", args!["John"]))?;
+///     tx.commit()
+///   })
+///   .join()
+///   .unwrap()?;
+///   # Ok(())
+///   # }
+/// ```
+pub type SyncOwnedTransaction = SyncTx<Arc<SyncClient>>;
+
+impl<C: Deref<Target = SyncClient>> SyncTx<C> {
+    pub fn new(client: C, id: u64) -> Result<Self> {
+        Self::with_behavior(client, id, TransactionBehavior::default())
+    }
+
+    pub fn with_behavior(client: C, id: u64, behavior: TransactionBehavior) -> Result<Self> {
+        block_on(Tx::with_behavior(SyncHandle(client), id, behavior)).map(Self)
     }
 
     /// Executes a statement within the current transaction.
@@ -81,16 +246,515 @@ impl<'a> SyncTransaction<'a> {
     ///   # }
     /// ```
     pub fn execute(&self, stmt: impl Into<Statement>) -> Result<ResultSet> {
-        self.client.execute_in_transaction(self.id, stmt.into())
+        block_on(self.0.execute(stmt))
+    }
+
+    /// Executes several statements within the current transaction, see [`Tx::batch`].
+    pub fn batch(
+        &self,
+        stmts: impl IntoIterator<Item = impl Into<Statement>>,
+    ) -> Result<Vec<ResultSet>> {
+        block_on(self.0.batch(stmts))
+    }
+
+    /// Starts a nested transaction, with a savepoint named `name`.
+    pub fn savepoint(&self, name: &str) -> Result<SyncSavepoint<'_>> {
+        block_on(self.0.savepoint(name)).map(SyncSavepoint)
     }
 
     /// Commits the transaction to the database.
     pub fn commit(self) -> Result<()> {
-        self.client.commit_transaction(self.id)
+        block_on(self.0.commit())
     }
 
     /// Rolls back the transaction, cancelling any of its side-effects.
     pub fn rollback(self) -> Result<()> {
-        self.client.rollback_transaction(self.id)
+        block_on(self.0.rollback())
+    }
+}
+
+// Collects the statements of a batch, rejecting writes in read-only transactions.
+fn batch_statements(
+    read_only: bool,
+    stmts: impl IntoIterator<Item = impl Into<Statement>>,
+) -> Result<Vec<Statement>> {
+    let stmts: Vec<Statement> = stmts.into_iter().map(Into::into).collect();
+    for stmt in &stmts {
+        check_read_only(read_only, stmt)?;
+    }
+    Ok(stmts)
+}
+
+/// Savepoints dropped without being released or rolled back.
+///
+/// Dropping cannot wait for the database, so they are rolled back
+/// before the next statement of their transaction.
+#[derive(Default)]
+struct DroppedSavepoints(Mutex<Vec<String>>);
+
+impl DroppedSavepoints {
+    fn push(&self, name: &str) {
+        tracing::warn!("Savepoint {name} dropped without release or rollback, rolling it back");
+        self.0.lock().unwrap().push(name.to_string());
+    }
+
+    // Statements rolling back the dropped savepoints, innermost first.
+    fn take(&self) -> Vec<Statement> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+            .into_iter()
+            .flat_map(|name| {
+                [
+                    Statement::new(format!("ROLLBACK TO {name}")),
+                    Statement::new(format!("RELEASE {name}")),
+                ]
+            })
+            .collect()
+    }
+
+    async fn roll_back(&self, client: &Client, id: u64) -> Result<()> {
+        for stmt in self.take() {
+            client.execute_in_transaction(id, stmt).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, client: &Client, id: u64, stmt: Statement) -> Result<ResultSet> {
+        self.roll_back(client, id).await?;
+        client.execute_in_transaction(id, stmt).await
+    }
+
+    async fn batch(
+        &self,
+        client: &Client,
+        id: u64,
+        stmts: Vec<Statement>,
+    ) -> Result<Vec<ResultSet>> {
+        self.roll_back(client, id).await?;
+        client.batch_in_transaction(id, stmts).await
+    }
+}
+
+// Quotes a savepoint name as an SQL identifier.
+fn savepoint_name(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A transaction nested in an interactive transaction, using a savepoint.
+///
+/// Releasing it keeps its changes in the enclosing transaction, and rolling it back
+/// cancels them without affecting the rest of the enclosing transaction. A savepoint
+/// dropped without being released or rolled back is rolled back.
+///
+/// # Examples
+///
+/// ```
+/// # async fn f() -> libsql_client::Result<()> {
+/// let db = libsql_client::Client::in_memory()?;
+/// # db.execute("CREATE TABLE users(name TEXT)").await?;
+/// let tx = db.transaction().await?;
+/// tx.execute("INSERT INTO users VALUES ('John')").await?;
+/// let sp = tx.savepoint("jane").await?;
+/// sp.execute("INSERT INTO users VALUES ('Jane')").await?;
+/// sp.rollback().await?;
+/// tx.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Savepoint<'a> {
+    client: &'a Client,
+    id: u64,
+    dropped: &'a DroppedSavepoints,
+    name: String,
+    read_only: bool,
+    finished: bool,
+}
+
+impl<'a> Savepoint<'a> {
+    async fn new(
+        client: &'a Client,
+        id: u64,
+        dropped: &'a DroppedSavepoints,
+        name: &str,
+        read_only: bool,
+    ) -> Result<Savepoint<'a>> {
+        let name = savepoint_name(name);
+        dropped
+            .execute(client, id, Statement::new(format!("SAVEPOINT {name}")))
+            .await?;
+        Ok(Self {
+            client,
+            id,
+            dropped,
+            name,
+            read_only,
+            finished: false,
+        })
+    }
+
+    /// Executes a statement within the savepoint.
+    pub async fn execute(&self, stmt: impl Into<Statement>) -> Result<ResultSet> {
+        let stmt = stmt.into();
+        check_read_only(self.read_only, &stmt)?;
+        self.dropped.execute(self.client, self.id, stmt).await
+    }
+
+    /// Executes several statements within the savepoint, see [`Tx::batch`].
+    pub async fn batch(
+        &self,
+        stmts: impl IntoIterator<Item = impl Into<Statement>>,
+    ) -> Result<Vec<ResultSet>> {
+        let stmts = batch_statements(self.read_only, stmts)?;
+        self.dropped.batch(self.client, self.id, stmts).await
+    }
+
+    /// Starts a transaction nested in this one, with a savepoint named `name`.
+    pub async fn savepoint(&self, name: &str) -> Result<Savepoint<'_>> {
+        Savepoint::new(self.client, self.id, self.dropped, name, self.read_only).await
+    }
+
+    // Sends `RELEASE` or `ROLLBACK TO` for the savepoint, which is allowed in read-only transactions.
+    async fn end(&self, command: &str) -> Result<ResultSet> {
+        let stmt = Statement::new(format!("{command} {}", self.name));
+        self.dropped.execute(self.client, self.id, stmt).await
+    }
+
+    /// Releases the savepoint, keeping its changes in the enclosing transaction.
+    pub async fn release(mut self) -> Result<()> {
+        self.end("RELEASE").await?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Rolls back the savepoint, cancelling the changes made since it started.
+    pub async fn rollback(mut self) -> Result<()> {
+        self.end("ROLLBACK TO").await?;
+        self.end("RELEASE").await?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.dropped.push(&self.name);
+        }
+    }
+}
+
+/// A synchronous flavor of [`Savepoint`].
+pub struct SyncSavepoint<'a>(Savepoint<'a>);
+
+impl SyncSavepoint<'_> {
+    /// Executes a statement within the savepoint.
+    pub fn execute(&self, stmt: impl Into<Statement>) -> Result<ResultSet> {
+        block_on(self.0.execute(stmt))
+    }
+
+    /// Executes several statements within the savepoint, see [`Tx::batch`].
+    pub fn batch(
+        &self,
+        stmts: impl IntoIterator<Item = impl Into<Statement>>,
+    ) -> Result<Vec<ResultSet>> {
+        block_on(self.0.batch(stmts))
+    }
+
+    /// Starts a transaction nested in this one, with a savepoint named `name`.
+    pub fn savepoint(&self, name: &str) -> Result<SyncSavepoint<'_>> {
+        block_on(self.0.savepoint(name)).map(SyncSavepoint)
+    }
+
+    /// Releases the savepoint, keeping its changes in the enclosing transaction.
+    pub fn release(self) -> Result<()> {
+        block_on(self.0.release())
+    }
+
+    /// Rolls back the savepoint, cancelling the changes made since it started.
+    pub fn rollback(self) -> Result<()> {
+        block_on(self.0.rollback())
+    }
+}
+
+#[cfg(all(test, feature = "local_backend"))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn dropped_transaction_is_rolled_back() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+
+        let tx = block_on(db.transaction()).unwrap();
+        block_on(tx.execute("INSERT INTO t VALUES (1)")).unwrap();
+        drop(tx);
+        block_on(db.execute("INSERT INTO t VALUES (2)")).unwrap();
+
+        // Dropping a transaction which was already ended is harmless
+        let tx = block_on(db.transaction()).unwrap();
+        block_on(tx.execute("ROLLBACK")).unwrap();
+        drop(tx);
+
+        let tx = block_on(db.transaction()).unwrap();
+        block_on(tx.execute("INSERT INTO t VALUES (3)")).unwrap();
+        block_on(tx.commit()).unwrap();
+
+        let rs = block_on(db.execute("SELECT x FROM t ORDER BY x")).unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(2,), (3,)]);
+    }
+
+    #[test]
+    fn savepoints() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let tx = block_on(db.transaction()).unwrap();
+        block_on(tx.execute("INSERT INTO t VALUES (1)")).unwrap();
+
+        let sp = block_on(tx.savepoint("a")).unwrap();
+        block_on(sp.execute("INSERT INTO t VALUES (2)")).unwrap();
+        let nested = block_on(sp.savepoint("a \"nested\"")).unwrap();
+        block_on(nested.execute("INSERT INTO t VALUES (3)")).unwrap();
+        block_on(nested.rollback()).unwrap();
+        block_on(sp.release()).unwrap();
+
+        let sp = block_on(tx.savepoint("b")).unwrap();
+        block_on(sp.execute("INSERT INTO t VALUES (4)")).unwrap();
+        let nested = block_on(sp.savepoint("c")).unwrap();
+        block_on(nested.execute("INSERT INTO t VALUES (5)")).unwrap();
+        drop(nested);
+        block_on(sp.execute("INSERT INTO t VALUES (6)")).unwrap();
+        drop(sp);
+        block_on(tx.commit()).unwrap();
+
+        let rs = block_on(db.execute("SELECT x FROM t ORDER BY x")).unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(1,), (2,)]);
+    }
+
+    #[test]
+    fn owned_transaction_moved_to_thread() {
+        let db = Arc::new(Client::in_memory().unwrap());
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let tx = block_on(db.clone().owned_transaction()).unwrap();
+        std::thread::spawn(move || {
+            block_on(async move {
+                tx.execute("INSERT INTO t VALUES (1)").await?;
+                tx.commit().await
+            })
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        let rs = block_on(db.execute("SELECT x FROM t")).unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(1,)]);
+
+        let db = Arc::new(SyncClient::in_memory().unwrap());
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let tx = db.clone().owned_transaction().unwrap();
+        std::thread::spawn(move || {
+            tx.execute("INSERT INTO t VALUES (2)")?;
+            tx.commit()
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        let rs = db.execute("SELECT x FROM t").unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(2,)]);
+    }
+
+    #[test]
+    fn owned_transactions_on_threads_run_one_at_a_time() {
+        let db = Arc::new(SyncClient::in_memory().unwrap());
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let tx = db.clone().owned_transaction().unwrap();
+        tx.execute("INSERT INTO t VALUES (1)").unwrap();
+        let other = std::thread::spawn({
+            let db = db.clone();
+            move || {
+                let tx = db.owned_transaction()?;
+                tx.execute("INSERT INTO t VALUES (2)")?;
+                tx.commit()
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        tx.rollback().unwrap();
+        other.join().unwrap().unwrap();
+        let rs = db.execute("SELECT x FROM t").unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(2,)]);
+    }
+
+    #[test]
+    fn sync_savepoints() {
+        let db = SyncClient::in_memory().unwrap();
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let tx = db.transaction().unwrap();
+        let sp = tx.savepoint("a").unwrap();
+        sp.execute("INSERT INTO t VALUES (1)").unwrap();
+        drop(sp);
+        let sp = tx.savepoint("b").unwrap();
+        sp.execute("INSERT INTO t VALUES (2)").unwrap();
+        sp.release().unwrap();
+        tx.commit().unwrap();
+
+        let rs = db.execute("SELECT x FROM t").unwrap();
+        assert_eq!(rs.into_typed::<(i64,)>().unwrap(), [(2,)]);
+    }
+
+    #[test]
+    fn read_only_transaction() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let tx = block_on(db.transaction_with(TransactionBehavior::ReadOnly)).unwrap();
+        block_on(tx.execute("SELECT * FROM t")).unwrap();
+        let err = block_on(tx.execute("INSERT INTO t VALUES (1)")).unwrap_err();
+        assert!(err.is_readonly(), "{err}");
+
+        let sp = block_on(tx.savepoint("a")).unwrap();
+        assert!(block_on(sp.execute("DELETE FROM t")).is_err());
+        block_on(sp.release()).unwrap();
+        block_on(tx.commit()).unwrap();
+
+        let tx = block_on(db.transaction_with(TransactionBehavior::Immediate)).unwrap();
+        block_on(tx.execute("INSERT INTO t VALUES (1)")).unwrap();
+        block_on(tx.commit()).unwrap();
+        let tx = block_on(db.transaction_with(TransactionBehavior::Exclusive)).unwrap();
+        block_on(tx.execute("INSERT INTO t VALUES (2)")).unwrap();
+        block_on(tx.rollback()).unwrap();
+
+        let rs = block_on(db.execute("SELECT count(*) FROM t")).unwrap();
+        assert_eq!(rs.scalar::<i64>().unwrap(), 1);
+    }
+
+    #[test]
+    fn read_only_transaction_statements() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let tx = block_on(db.transaction_with(TransactionBehavior::ReadOnly)).unwrap();
+        block_on(tx.execute("WITH y AS (SELECT x FROM t) SELECT * FROM y")).unwrap();
+        block_on(tx.execute("EXPLAIN QUERY PLAN SELECT * FROM t")).unwrap();
+        // PRAGMA statements are rejected, even those only reading the schema
+        for sql in [
+            "PRAGMA table_info(t)",
+            "PRAGMA user_version = 1",
+            "WITH y AS (SELECT 1) INSERT INTO t SELECT * FROM y",
+        ] {
+            let err = block_on(tx.execute(sql)).unwrap_err();
+            assert!(err.is_readonly(), "{sql}: {err}");
+        }
+        block_on(tx.commit()).unwrap();
+    }
+
+    #[test]
+    fn owned_transaction_with_behavior() {
+        let db = Arc::new(Client::in_memory().unwrap());
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let tx = block_on(
+            db.clone()
+                .owned_transaction_with(TransactionBehavior::ReadOnly),
+        )
+        .unwrap();
+        block_on(tx.execute("SELECT * FROM t")).unwrap();
+        let err = block_on(tx.execute("INSERT INTO t VALUES (1)")).unwrap_err();
+        assert!(err.is_readonly(), "{err}");
+        block_on(tx.commit()).unwrap();
+
+        let db = Arc::new(SyncClient::in_memory().unwrap());
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let tx = db
+            .clone()
+            .owned_transaction_with(TransactionBehavior::Immediate)
+            .unwrap();
+        tx.execute("INSERT INTO t VALUES (1)").unwrap();
+        tx.commit().unwrap();
+        let tx = db
+            .clone()
+            .owned_transaction_with(TransactionBehavior::ReadOnly)
+            .unwrap();
+        assert!(tx.execute("DELETE FROM t").unwrap_err().is_readonly());
+        tx.rollback().unwrap();
+        assert_eq!(
+            db.execute("SELECT count(*) FROM t")
+                .unwrap()
+                .scalar::<i64>()
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn with_transaction_retries_conflicts() {
+        use crate::client::RetryPolicy;
+        use crate::error::codes;
+
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x)")).unwrap();
+        let retry = RetryPolicy {
+            backoff: std::time::Duration::ZERO,
+            ..Default::default()
+        };
+
+        let mut attempts = 0;
+        let count: i64 = block_on(db.with_transaction_retry(&retry, |tx| {
+            attempts += 1;
+            let busy = attempts == 1;
+            Box::pin(async move {
+                tx.execute("INSERT INTO t VALUES (1)").await?;
+                if busy {
+                    return Err(Error::Sqlite {
+                        code: Some(codes::SQLITE_BUSY),
+                        message: "database is locked".into(),
+                    });
+                }
+                tx.execute("SELECT count(*) FROM t").await?.scalar()
+            })
+        }))
+        .unwrap();
+        assert_eq!((attempts, count), (2, 1));
+
+        let mut attempts = 0;
+        let err = block_on(db.with_transaction(|tx| {
+            attempts += 1;
+            Box::pin(async move {
+                tx.execute("INSERT INTO t VALUES (2)").await?;
+                tx.execute("INSERT INTO missing VALUES (3)").await
+            })
+        }))
+        .unwrap_err();
+        assert!(!err.is_busy());
+        assert_eq!(attempts, 1);
+
+        let rs = block_on(db.execute("SELECT count(*) FROM t")).unwrap();
+        assert_eq!(rs.scalar::<i64>().unwrap(), 1);
+    }
+
+    #[test]
+    fn batch_in_transaction() {
+        let db = Client::in_memory().unwrap();
+        block_on(db.execute("CREATE TABLE t(x INTEGER PRIMARY KEY)")).unwrap();
+        let tx = block_on(db.transaction()).unwrap();
+        let results = block_on(tx.batch([
+            "INSERT INTO t VALUES (1)",
+            "INSERT INTO t VALUES (2)",
+            "SELECT count(*) FROM t",
+        ]))
+        .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].last_insert_rowid, Some(2));
+        assert_eq!(results[2].scalar::<i64>().unwrap(), 2);
+
+        match block_on(tx.batch(["INSERT INTO t VALUES (3)", "INSERT INTO t VALUES (1)"])) {
+            Err(Error::Batch { step, source, .. }) => {
+                assert_eq!(step, 1);
+                assert!(source.is_constraint_violation());
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        block_on(tx.rollback()).unwrap();
+
+        let db = SyncClient::in_memory().unwrap();
+        db.execute("CREATE TABLE t(x)").unwrap();
+        let tx = db.transaction_with(TransactionBehavior::ReadOnly).unwrap();
+        let err = tx.batch(["SELECT 1", "DELETE FROM t"]).unwrap_err();
+        assert!(err.is_readonly(), "{err}");
+        assert_eq!(tx.batch(["SELECT 1", "SELECT 2"]).unwrap().len(), 2);
     }
 }
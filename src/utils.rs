@@ -1,3 +1,7 @@
+use fallible_iterator::FallibleIterator;
+use sqlite3_parser::ast::{Cmd, Stmt};
+use sqlite3_parser::lexer::sql::{Parser, TokenType, Tokenizer};
+use sqlite3_parser::lexer::Scanner;
 use url::Url;
 
 pub(crate) fn pop_query_param(url: &mut Url, param: String) -> Option<String> {
@@ -18,11 +22,178 @@ pub(crate) fn pop_query_param(url: &mut Url, param: String) -> Option<String> {
     value
 }
 
+/// Randomizes `delay` to between half and all of its value.
+pub(crate) fn with_jitter(delay: std::time::Duration) -> std::time::Duration {
+    use std::hash::{BuildHasher, Hasher};
+    // RandomState is seeded randomly, which is good enough to spread retries apart
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    delay / 2 + delay.mul_f64(random as f64 / u64::MAX as f64 / 2.0)
+}
+
+/// Waits for `duration` without blocking the executor: on the timer of the Tokio runtime
+/// running the caller if there is one, otherwise on a single thread shared by all waits.
+#[cfg(feature = "local_backend")]
+pub(crate) async fn sleep(duration: std::time::Duration) {
+    #[cfg(feature = "tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::sleep(duration).await;
+    }
+    let (tx, rx) = futures::channel::oneshot::channel();
+    timer()
+        .send((std::time::Instant::now() + duration, tx))
+        .ok();
+    rx.await.ok();
+}
+
+#[cfg(feature = "local_backend")]
+type Wait = (std::time::Instant, futures::channel::oneshot::Sender<()>);
+
+// Sends waits to the timer thread, started by the first one.
+#[cfg(feature = "local_backend")]
+fn timer() -> &'static std::sync::mpsc::Sender<Wait> {
+    use std::sync::mpsc::RecvTimeoutError;
+    static TIMER: std::sync::OnceLock<std::sync::mpsc::Sender<Wait>> = std::sync::OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (sender, requests) = std::sync::mpsc::channel::<Wait>();
+        std::thread::spawn(move || {
+            let mut waits: Vec<Wait> = Vec::new();
+            loop {
+                let now = std::time::Instant::now();
+                let (expired, pending) = waits
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|(deadline, _)| *deadline <= now);
+                for (_, tx) in expired {
+                    tx.send(()).ok();
+                }
+                waits = pending;
+                let request = match waits.iter().map(|(deadline, _)| *deadline).min() {
+                    Some(deadline) => requests.recv_timeout(deadline - now),
+                    None => requests.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match request {
+                    Ok(wait) => waits.push(wait),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+        sender
+    })
+}
+
+/// Returns true if every statement in `sql` only reads from the database: a `SELECT`,
+/// including one with a `WITH` clause, or an `EXPLAIN`. Statements that cannot be parsed
+/// are not considered read-only, and neither are `PRAGMA` statements, since many of them
+/// change the database or the connection.
+pub(crate) fn is_read_only(sql: &str) -> bool {
+    let mut parser = Parser::new(sql.as_bytes());
+    let mut found_any = false;
+    loop {
+        match parser.next() {
+            Ok(Some(Cmd::Stmt(Stmt::Select(_)) | Cmd::Explain(_) | Cmd::ExplainQueryPlan(_))) => {
+                found_any = true
+            }
+            Ok(None) => return found_any,
+            _ => return false,
+        }
+    }
+}
+
+/// Returns true if `sql` is a statement beginning or ending a transaction,
+/// like `BEGIN`, `COMMIT`, `END` or `ROLLBACK` (but not `ROLLBACK TO` a savepoint).
+pub(crate) fn is_transaction_control(sql: &str) -> bool {
+    let mut parser = Parser::new(sql.as_bytes());
+    matches!(
+        parser.next(),
+        Ok(Some(Cmd::Stmt(
+            Stmt::Begin(..)
+                | Stmt::Commit(..)
+                | Stmt::Rollback {
+                    savepoint_name: None,
+                    ..
+                }
+        )))
+    )
+}
+
+/// Splits `sql` into its individual statements, ignoring empty ones.
+///
+/// Statements end with a semicolon, except inside the body of a trigger,
+/// following the same rules as `sqlite3_complete()`.
+pub(crate) fn split_statements(sql: &str) -> crate::Result<Vec<String>> {
+    use TokenType::*;
+
+    #[derive(Clone, Copy)]
+    enum State {
+        Start,
+        Normal,
+        Explain,
+        Create,
+        Trigger,
+        Semi,
+        End,
+    }
+
+    let mut scanner = Scanner::new(Tokenizer::new());
+    let mut statements = Vec::new();
+    let mut state = State::Start;
+    let mut begin = None;
+    loop {
+        let (start, token, _) = scanner
+            .scan(sql.as_bytes())
+            .map_err(|e| crate::Error::Sqlite {
+                code: Some(crate::error::codes::SQLITE_ERROR),
+                message: e.to_string(),
+            })?;
+        let Some((_, token)) = token else {
+            break;
+        };
+        state = match (state, token) {
+            (State::Trigger | State::Semi, TK_SEMI) => State::Semi,
+            (State::Semi, TK_END) => State::End,
+            (_, TK_SEMI) => {
+                if let Some(begin) = begin.take() {
+                    statements.push(sql[begin..start].trim().to_string());
+                }
+                State::Start
+            }
+            (State::Trigger | State::Semi | State::End, _) => State::Trigger,
+            (State::Start, TK_EXPLAIN) => State::Explain,
+            (State::Start | State::Explain, TK_CREATE) => State::Create,
+            (State::Create, TK_TEMP) => State::Create,
+            (State::Create, TK_TRIGGER) => State::Trigger,
+            (State::Explain, TK_EXPLAIN | TK_TEMP | TK_TRIGGER | TK_END) => State::Normal,
+            (State::Explain, _) => State::Explain,
+            _ => State::Normal,
+        };
+        if token != TK_SEMI && begin.is_none() {
+            begin = Some(start);
+        }
+    }
+    if let Some(begin) = begin {
+        statements.push(sql[begin..].trim().to_string());
+    }
+    Ok(statements)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use url::Url;
 
+    #[cfg(feature = "local_backend")]
+    #[test]
+    fn sleep_without_runtime() {
+        let duration = std::time::Duration::from_millis(50);
+        let start = std::time::Instant::now();
+        let sleeps = (1..=100).map(|i| sleep(duration * (i % 3)));
+        futures::executor::block_on(futures::future::join_all(sleeps));
+        assert!(start.elapsed() >= duration * 2);
+        assert!(start.elapsed() < duration * 20);
+    }
+
     #[test]
     fn test_pop_query_param_existing() {
         let mut url = Url::parse("http://turso.io/?super=yes&sqld=yo").unwrap();
@@ -41,4 +212,58 @@ mod tests {
         assert_eq!(result, None);
         assert_eq!(url.as_str(), "http://turso.io/?super=yes&sqld=yo");
     }
+
+    #[test]
+    fn test_split_statements() {
+        let sql = "
+            -- schema
+            CREATE TABLE t(id INTEGER PRIMARY KEY, v TEXT);;
+            CREATE TEMP TRIGGER trg AFTER INSERT ON t BEGIN
+                UPDATE t SET v = 'a;b' WHERE id = new.id;
+                DELETE FROM t WHERE id < 0;
+            END;
+            INSERT INTO t(v) VALUES ('end'); SELECT 1
+        ";
+        let statements = split_statements(sql).unwrap();
+        assert_eq!(statements.len(), 4);
+        assert_eq!(
+            statements[0],
+            "CREATE TABLE t(id INTEGER PRIMARY KEY, v TEXT)"
+        );
+        assert!(statements[1].starts_with("CREATE TEMP TRIGGER"));
+        assert!(statements[1].ends_with("END"));
+        assert_eq!(statements[2], "INSERT INTO t(v) VALUES ('end')");
+        assert_eq!(statements[3], "SELECT 1");
+
+        assert!(split_statements("  ;; -- nothing").unwrap().is_empty());
+        assert!(split_statements("SELECT 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        assert!(is_read_only("SELECT * FROM t WHERE id = ?"));
+        assert!(is_read_only("WITH x AS (SELECT 1) SELECT * FROM x"));
+        assert!(is_read_only("EXPLAIN DELETE FROM t"));
+        assert!(!is_read_only("DELETE FROM t"));
+        assert!(!is_read_only("SELECT 1; INSERT INTO t VALUES (1)"));
+        assert!(!is_read_only(
+            "WITH x AS (SELECT 1) INSERT INTO t SELECT * FROM x"
+        ));
+        assert!(!is_read_only("PRAGMA user_version = 1"));
+        assert!(!is_read_only("PRAGMA table_info(t)"));
+        assert!(!is_read_only("BEGIN"));
+        assert!(!is_read_only("SELEKT"));
+        assert!(!is_read_only(""));
+    }
+
+    #[test]
+    fn test_is_transaction_control() {
+        assert!(is_transaction_control("BEGIN IMMEDIATE"));
+        assert!(is_transaction_control("commit"));
+        assert!(is_transaction_control("END TRANSACTION"));
+        assert!(is_transaction_control("ROLLBACK"));
+        assert!(!is_transaction_control("ROLLBACK TO sp"));
+        assert!(!is_transaction_control("SAVEPOINT sp"));
+        assert!(!is_transaction_control("CREATE TABLE t(x)"));
+    }
 }
@@ -0,0 +1,456 @@
+//! `FromValue` and `ToValue` convert between Rust types and [`Value`]s.
+//!
+//! They are implemented for the usual Rust types, and for `uuid::Uuid` with the `uuid` feature.
+//! They can be implemented for your own types,
+//! which can then be bound with [`Statement::with_args`](crate::Statement::with_args) and read
+//! with [`Row::try_get`](crate::Row::try_get). This module can also be used with
+//! `#[serde(with = "libsql_client::value")]` to (de)serialize such types with
+//! [`de`](crate::de) and [`ser`](crate::ser).
+//!
+//! # Examples
+//!
+//! ```
+//! use libsql_client::{FromValue, Statement, ToValue, Value};
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Money {
+//!     cents: i64,
+//! }
+//!
+//! impl ToValue for Money {
+//!     fn to_value(&self) -> Value {
+//!         self.cents.to_value()
+//!     }
+//! }
+//!
+//! impl FromValue<'_> for Money {
+//!     fn from_value(value: &Value) -> libsql_client::Result<Self> {
+//!         i64::from_value(value).map(|cents| Money { cents })
+//!     }
+//! }
+//!
+//! let db = libsql_client::SyncClient::in_memory().unwrap();
+//! db.execute("create table prices(price integer)").unwrap();
+//! db.execute(Statement::with_args("insert into prices values (?)", &[Money { cents: 450 }]))
+//!     .unwrap();
+//! let rs = db.execute("select price from prices").unwrap();
+//! assert_eq!(rs.rows[0].try_get::<Money>(0).unwrap(), Money { cents: 450 });
+//! ```
+
+use std::fmt;
+
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::Serializer;
+
+use crate::{Error, Result, Value};
+
+/// Conversion from a [`Value`], possibly borrowing from it
+pub trait FromValue<'a>: Sized {
+    fn from_value(value: &'a Value) -> Result<Self>;
+}
+
+/// Conversion to a [`Value`]
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+fn unexpected<T>(value: &Value, expected: &str) -> Result<T> {
+    Err(Error::Conversion(format!(
+        "cannot convert {value} to {expected}"
+    )))
+}
+
+impl<'a> FromValue<'a> for Value {
+    fn from_value(value: &'a Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl<'a> FromValue<'a> for &'a Value {
+    fn from_value(value: &'a Value) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl<'a, T: FromValue<'a>> FromValue<'a> for Option<T> {
+    fn from_value(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl FromValue<'_> for $ty {
+                fn from_value(value: &Value) -> Result<Self> {
+                    match value {
+                        Value::Integer { value } => (*value).try_into().map_err(|_| {
+                            Error::Conversion(format!(
+                                "{value} is out of range for {}",
+                                stringify!($ty)
+                            ))
+                        }),
+                        value => unexpected(value, stringify!($ty)),
+                    }
+                }
+            }
+
+            impl ToValue for $ty {
+                fn to_value(&self) -> Value {
+                    Value::Integer {
+                        value: *self as i64,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, usize);
+
+impl FromValue<'_> for u64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Integer { value } => (*value)
+                .try_into()
+                .map_err(|_| Error::Conversion(format!("{value} is out of range for u64"))),
+            value => unexpected(value, "u64"),
+        }
+    }
+}
+
+impl FromValue<'_> for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Float { value } => Ok(*value),
+            value => unexpected(value, "f64"),
+        }
+    }
+}
+
+impl FromValue<'_> for f32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        f64::from_value(value).map(|value| value as f32)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float { value: *self }
+    }
+}
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float {
+            value: *self as f64,
+        }
+    }
+}
+
+/// Booleans are stored as 0 or 1
+impl FromValue<'_> for bool {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Integer { value: 0 } => Ok(false),
+            Value::Integer { value: 1 } => Ok(true),
+            value => unexpected(value, "bool"),
+        }
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Integer {
+            value: *self as i64,
+        }
+    }
+}
+
+impl FromValue<'_> for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        <&str>::from_value(value).map(str::to_string)
+    }
+}
+
+impl<'a> FromValue<'a> for &'a str {
+    fn from_value(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Text { value } => Ok(value),
+            value => unexpected(value, "str"),
+        }
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Text {
+            value: self.to_string(),
+        }
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        self.as_str().to_value()
+    }
+}
+
+impl FromValue<'_> for Vec<u8> {
+    fn from_value(value: &Value) -> Result<Self> {
+        <&[u8]>::from_value(value).map(<[u8]>::to_vec)
+    }
+}
+
+impl<'a> FromValue<'a> for &'a [u8] {
+    fn from_value(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Blob { value } => Ok(value),
+            value => unexpected(value, "blob"),
+        }
+    }
+}
+
+impl ToValue for [u8] {
+    fn to_value(&self) -> Value {
+        Value::Blob {
+            value: self.to_vec(),
+        }
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        self.as_slice().to_value()
+    }
+}
+
+impl ToValue for () {
+    fn to_value(&self) -> Value {
+        Value::Null
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+/// UUIDs are read from TEXT in any of the formats accepted by [`uuid::Uuid::parse_str`]
+/// or from 16-byte BLOBs
+#[cfg(feature = "uuid")]
+impl FromValue<'_> for uuid::Uuid {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Text { value } => uuid::Uuid::parse_str(value)
+                .map_err(|e| Error::Conversion(format!("cannot convert {value} to uuid: {e}"))),
+            Value::Blob { value } => uuid::Uuid::from_slice(value)
+                .map_err(|e| Error::Conversion(format!("cannot convert blob to uuid: {e}"))),
+            value => unexpected(value, "uuid"),
+        }
+    }
+}
+
+/// UUIDs are stored as hyphenated TEXT, like [`ser`](crate::ser) does
+#[cfg(feature = "uuid")]
+impl ToValue for uuid::Uuid {
+    fn to_value(&self) -> Value {
+        self.hyphenated().to_string().to_value()
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+/// Serializes any type implementing [`ToValue`], for use with `#[serde(with = "libsql_client::value")]`
+pub fn serialize<T: ToValue, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    match value.to_value() {
+        Value::Null => serializer.serialize_none(),
+        Value::Integer { value } => serializer.serialize_i64(value),
+        Value::Float { value } => serializer.serialize_f64(value),
+        Value::Text { value } => serializer.serialize_str(&value),
+        Value::Blob { value } => serializer.serialize_bytes(&value),
+    }
+}
+
+/// Deserializes any type implementing [`FromValue`], for use with `#[serde(with = "libsql_client::value")]`
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: for<'a> FromValue<'a>,
+    D: Deserializer<'de>,
+{
+    let value = deserializer.deserialize_any(ValueVisitor)?;
+    T::from_value(&value).map_err(serde::de::Error::custom)
+}
+
+/// Rebuilds the [`Value`] of a column from the serde data model
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an SQLite value")
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(value.to_value())
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(value.to_value())
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Value, E> {
+        let value = i64::try_from(value)
+            .map_err(|_| E::custom(format!("{value} is out of range for i64")))?;
+        Ok(value.to_value())
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(value.to_value())
+    }
+
+    fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(value.to_value())
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<Value, E> {
+        Ok(value.to_value())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(Value::Blob { value: bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::Row;
+
+    #[derive(Debug, PartialEq)]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    impl ToValue for Role {
+        fn to_value(&self) -> Value {
+            match self {
+                Role::Admin => "admin",
+                Role::Guest => "guest",
+            }
+            .to_value()
+        }
+    }
+
+    impl FromValue<'_> for Role {
+        fn from_value(value: &Value) -> Result<Self> {
+            match <&str>::from_value(value)? {
+                "admin" => Ok(Role::Admin),
+                "guest" => Ok(Role::Guest),
+                other => Err(Error::Conversion(format!("unknown role {other}"))),
+            }
+        }
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(i64::from_value(&42.to_value()).unwrap(), 42);
+        assert_eq!(u8::from_value(&255u8.to_value()).unwrap(), 255);
+        assert!(u8::from_value(&256.to_value()).is_err());
+        assert!(i64::from_value(&"42".to_value()).is_err());
+        assert!(bool::from_value(&true.to_value()).unwrap());
+        assert_eq!(f64::from_value(&4.5.to_value()).unwrap(), 4.5);
+        assert_eq!(<&str>::from_value(&"foo".to_value()).unwrap(), "foo");
+        assert_eq!(
+            Vec::<u8>::from_value(&vec![1u8, 2].to_value()).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(Option::<i64>::from_value(&Value::Null).unwrap(), None);
+        assert!(matches!(None::<i64>.to_value(), Value::Null));
+        assert!(matches!(Some("foo").to_value(), Value::Text { value } if value == "foo"));
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid() {
+        let id = uuid::Uuid::from_u128(0x1234_5678_9abc_def0_1234_5678_9abc_def0);
+        let value = id.to_value();
+        assert!(
+            matches!(&value, Value::Text { value } if value == "12345678-9abc-def0-1234-56789abcdef0")
+        );
+        assert_eq!(uuid::Uuid::from_value(&value).unwrap(), id);
+        assert_eq!(
+            uuid::Uuid::from_value(&id.as_bytes().to_value()).unwrap(),
+            id
+        );
+        assert!(uuid::Uuid::from_value(&"not a uuid".to_value()).is_err());
+        assert!(uuid::Uuid::from_value(&vec![1u8, 2].to_value()).is_err());
+        assert!(uuid::Uuid::from_value(&42.to_value()).is_err());
+    }
+
+    #[test]
+    fn serde_with_value() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct User {
+            #[serde(with = "crate::value")]
+            role: Role,
+            #[serde(with = "crate::value")]
+            data: Vec<u8>,
+        }
+
+        let user = User {
+            role: Role::Guest,
+            data: vec![1, 2, 3],
+        };
+        let args: HashMap<String, Value> = crate::ser::to_named_args(&user)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert!(matches!(&args[":role"], Value::Text { value } if value == "guest"));
+        assert!(matches!(&args[":data"], Value::Blob { value } if value == &[1, 2, 3]));
+
+        let columns = vec!["role".to_string(), "data".to_string()];
+        let row = Row::from_values(&columns, vec![args[":role"].clone(), args[":data"].clone()]);
+        let user: User = crate::de::from_columns(&columns, &row).unwrap();
+        assert_eq!(user.role, Role::Guest);
+        assert_eq!(user.data, vec![1, 2, 3]);
+        assert_ne!(user.role, Role::Admin);
+    }
+}
@@ -1,22 +1,68 @@
-use anyhow::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::Either;
+use futures::Stream;
 use worker::*;
 
+use crate::http::BodyStream;
 use crate::proto::pipeline;
+use crate::Error as ClientError;
 
 #[derive(Clone, Debug)]
 pub struct HttpClient;
 
+/// Aborts the fetch it guards when dropped, so that a request that timed out
+/// or was cancelled by the caller does not keep running in the background.
+struct AbortOnDrop(Option<AbortController>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(controller) = self.0.take() {
+            controller.abort();
+        }
+    }
+}
+
+/// Body of a streamed response, which aborts the fetch when dropped before reaching its end.
+struct FetchBody {
+    stream: Pin<Box<ByteStream>>,
+    _guard: AbortOnDrop,
+}
+
+// Workers run on a single thread, so the body is never actually sent to another one
+unsafe impl Send for FetchBody {}
+
+impl Stream for FetchBody {
+    type Item = crate::Result<Vec<u8>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream.as_mut().poll_next(cx).map(|chunk| {
+            chunk.map(|chunk| chunk.map_err(|e| ClientError::Transport(e.to_string().into())))
+        })
+    }
+}
+
 impl HttpClient {
     pub fn new() -> Self {
         Self
     }
 
-    pub async fn send(
+    /// Workers cannot limit the time spent connecting, so `timeouts.connect` is ignored.
+    /// The request timeout is passed to [HttpClient::send] instead.
+    pub fn with_timeouts(_timeouts: &crate::Timeouts) -> Self {
+        Self
+    }
+
+    // Posts `body` to `url`, failing if the server does not answer with 200 OK.
+    async fn post(
         &self,
-        url: String,
+        url: &str,
         auth: String,
         body: String,
-    ) -> Result<pipeline::ServerMsg> {
+        signal: &AbortSignal,
+    ) -> crate::Result<Response> {
         let mut headers = Headers::new();
         headers.append("Authorization", &auth).ok();
 
@@ -27,20 +73,77 @@ impl HttpClient {
             method: Method::Post,
             redirect: RequestRedirect::Follow,
         };
-        let req =
-            Request::new_with_init(&url, &request_init).map_err(|e| anyhow::anyhow!("{e}"))?;
+        let req = Request::new_with_init(url, &request_init)
+            .map_err(|e| ClientError::Transport(e.to_string().into()))?;
         let mut response = Fetch::Request(req)
-            .send()
+            .send_with_signal(signal)
             .await
-            .map_err(|e| anyhow::anyhow!("{e}"))?;
+            .map_err(|e| ClientError::Transport(e.to_string().into()))?;
         if response.status_code() != 200 {
-            anyhow::bail!("Status {}", response.status_code());
+            return Err(ClientError::Http {
+                status: response.status_code(),
+                message: response.text().await.unwrap_or_default(),
+            });
         }
+        Ok(response)
+    }
 
-        let resp: String = response.text().await.map_err(|e| anyhow::anyhow!("{e}"))?;
+    pub async fn send(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+        timeout: Option<Duration>,
+    ) -> crate::Result<pipeline::ServerMsg> {
+        let controller = AbortController::default();
+        let signal = controller.signal();
+        let _guard = AbortOnDrop(Some(controller));
+        let exchange = async {
+            self.post(&url, auth, body, &signal)
+                .await?
+                .text()
+                .await
+                .map_err(|e| ClientError::Transport(e.to_string().into()))
+        };
+
+        let resp: String = match timeout {
+            Some(timeout) => {
+                let delay = Delay::from(timeout);
+                futures::pin_mut!(exchange, delay);
+                match futures::future::select(exchange, delay).await {
+                    Either::Left((resp, _)) => resp?,
+                    Either::Right(_) => {
+                        return Err(ClientError::Timeout(format!(
+                            "request to {url} did not complete in {timeout:?}"
+                        )))
+                    }
+                }
+            }
+            None => exchange.await?,
+        };
         let response: pipeline::ServerMsg = serde_json::from_str(&resp)?;
         Ok(response)
     }
+
+    /// Like [HttpClient::send], but returns the response body as it arrives.
+    pub async fn send_streaming(
+        &self,
+        url: String,
+        auth: String,
+        body: String,
+    ) -> crate::Result<BodyStream> {
+        let controller = AbortController::default();
+        let signal = controller.signal();
+        let guard = AbortOnDrop(Some(controller));
+        let mut response = self.post(&url, auth, body, &signal).await?;
+        let stream = response
+            .stream()
+            .map_err(|e| ClientError::Transport(e.to_string().into()))?;
+        Ok(Box::pin(FetchBody {
+            stream: Box::pin(stream),
+            _guard: guard,
+        }))
+    }
 }
 
 impl Default for HttpClient {
@@ -0,0 +1,121 @@
+//! Checks that the local backend reports the same results as sqld for the same statements.
+//!
+//! The statements always run against an in-memory local database and their results are
+//! compared with the expected ones. When `LIBSQL_CLIENT_URL` points to a remote database,
+//! they also run there, and the remote results must match the local ones.
+
+use libsql_client::{Client, ResultSet};
+
+/// Statement to run, with `{t}` standing for a table name unique to the run,
+/// and its expected rows affected, last inserted rowid and number of rows.
+struct Case {
+    sql: &'static str,
+    rows_affected: u64,
+    last_insert_rowid: Option<i64>,
+    rows: usize,
+}
+
+const fn case(
+    sql: &'static str,
+    rows_affected: u64,
+    last_insert_rowid: Option<i64>,
+    rows: usize,
+) -> Case {
+    Case {
+        sql,
+        rows_affected,
+        last_insert_rowid,
+        rows,
+    }
+}
+
+const CASES: &[Case] = &[
+    case(
+        "CREATE TABLE {t}(id INTEGER PRIMARY KEY, v TEXT UNIQUE)",
+        0,
+        None,
+        0,
+    ),
+    case("INSERT INTO {t}(v) VALUES ('a'), ('b')", 2, Some(2), 0),
+    case("SELECT * FROM {t}", 0, None, 2),
+    // The row replaced because of the conflict is not counted
+    case("REPLACE INTO {t}(id, v) VALUES (3, 'a')", 1, Some(3), 0),
+    case(
+        "INSERT INTO {t}(v) VALUES ('c') ON CONFLICT(v) DO UPDATE SET v = 'c!'",
+        1,
+        Some(4),
+        0,
+    ),
+    case(
+        "INSERT INTO {t}(v) VALUES ('c') ON CONFLICT(v) DO UPDATE SET v = 'c!'",
+        1,
+        Some(4),
+        0,
+    ),
+    case("INSERT INTO {t}(v) VALUES ('d') RETURNING id", 1, Some(5), 1),
+    case(
+        "WITH x(v) AS (VALUES ('e')) INSERT INTO {t}(v) SELECT v FROM x",
+        1,
+        Some(6),
+        0,
+    ),
+    case("UPDATE {t} SET v = v || '?' WHERE id > 4", 2, Some(6), 0),
+    case("INSERT OR IGNORE INTO {t}(v) VALUES ('b')", 0, None, 0),
+    case("DELETE FROM {t} WHERE id = 100", 0, None, 0),
+    case("CREATE TABLE {t}_log(msg TEXT)", 0, None, 0),
+    case(
+        "CREATE TRIGGER {t}_trigger AFTER DELETE ON {t} BEGIN INSERT INTO {t}_log VALUES (old.v); END",
+        0,
+        None,
+        0,
+    ),
+    // Rows changed by the trigger are not counted, and its rowid is not reported
+    case("DELETE FROM {t} WHERE id = 2", 1, Some(6), 0),
+    case("SELECT * FROM {t}_log", 0, None, 1),
+];
+
+async fn run(db: &Client, table: &str) -> Vec<ResultSet> {
+    let mut results = Vec::new();
+    for case in CASES {
+        let sql = case.sql.replace("{t}", table);
+        let result = db
+            .execute(sql.as_str())
+            .await
+            .unwrap_or_else(|e| panic!("{sql}: {e}"));
+        results.push(result);
+    }
+    db.execute(format!("DROP TABLE {table}")).await.unwrap();
+    db.execute(format!("DROP TABLE {table}_log")).await.unwrap();
+    results
+}
+
+#[tokio::test]
+async fn local_matches_sqld() {
+    let table = format!("conformance_{}", rand::random::<u32>());
+
+    let local = run(&Client::in_memory().unwrap(), &table).await;
+    for (case, result) in CASES.iter().zip(&local) {
+        assert_eq!(result.rows_affected, case.rows_affected, "{}", case.sql);
+        assert_eq!(
+            result.last_insert_rowid, case.last_insert_rowid,
+            "{}",
+            case.sql
+        );
+        assert_eq!(result.rows.len(), case.rows, "{}", case.sql);
+    }
+
+    if std::env::var("LIBSQL_CLIENT_URL").is_err() {
+        return;
+    }
+    let remote = run(&Client::from_env().await.unwrap(), &table).await;
+    for ((case, local), remote) in CASES.iter().zip(&local).zip(&remote) {
+        assert_eq!(remote.rows_affected, local.rows_affected, "{}", case.sql);
+        assert_eq!(
+            remote.last_insert_rowid, local.last_insert_rowid,
+            "{}",
+            case.sql
+        );
+        assert_eq!(remote.columns, local.columns, "{}", case.sql);
+        assert_eq!(remote.rows.len(), local.rows.len(), "{}", case.sql);
+    }
+}